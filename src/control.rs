@@ -1,10 +1,39 @@
-use std::sync::mpsc::{Receiver, SyncSender};
+use std::{
+    sync::mpsc::{Receiver, SyncSender},
+    time::{Duration, Instant},
+};
 
 use image::RgbImage;
+use serde::{Deserialize, Serialize};
 
 pub enum ToBrain {
     NextFrame(RgbImage),
+    /// The next chunk of the game's sound
+    Audio(AudioBuffer),
+    /// Something happened to the game window
+    Window(WindowEvent),
 }
+/// A change of the game window that matters for fishing
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WindowEvent {
+    /// The window was minimised or hidden, no frames arrive until it's shown again
+    Hidden,
+    Shown,
+    /// The window was closed, no frames arrive until a window with its name shows up again
+    Closed,
+    /// A new window with the name of the closed one showed up
+    Found,
+    /// The window has a new width and height, in px
+    Resized([u32; 2]),
+}
+/// Mono audio samples in the range -1..1
+pub struct AudioBuffer {
+    pub sample_rate: u32,
+    pub samples: Vec<f32>,
+    /// When the last sample was recorded, buffers can queue behind frames on their way to the brain
+    pub captured: Instant,
+}
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ToController {
     /// Move the mouse to a position relative to the target window
     MoveMouse([i32; 2]),
@@ -13,10 +42,54 @@ pub enum ToController {
     /// Sends a message that the BACKTICK key was pressed
     CastHook,
 }
+/// How the controller delivers input to the game window.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum InputMethod {
+    /// Events sent straight to the window. Works while the game is in the background, but
+    /// some games, like anything running under Wine, ignore events that don't come from a
+    /// real device.
+    #[default]
+    SendEvent,
+    /// Input faked through the XTEST extension as if it came from a real device. Every game
+    /// takes it, but it goes to whatever window has focus.
+    XTest,
+}
+
+/// How to capture and control the game window, as far as the backend supports it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WindowParams {
+    /// The X display the game is on, like `:1`, instead of the one `DISPLAY` names.
+    pub display: Option<String>,
+    pub input: InputMethod,
+    /// Capture the window off screen, so other windows covering it don't end up in the
+    /// frames. Needs the Composite extension of X11.
+    pub composite: bool,
+    /// Capture a frame only once the middle third of the window, where the bobber is
+    /// looked for, changed. Needs the DAMAGE extension of X11.
+    pub damage: bool,
+    /// Longest time between frames when capturing on damage, so the other checks of the
+    /// frame still happen while the water is still.
+    #[serde(with = "crate::profile::millis")]
+    pub max_frame_interval: Duration,
+}
+impl Default for WindowParams {
+    fn default() -> Self {
+        Self {
+            display: None,
+            input: InputMethod::default(),
+            composite: false,
+            damage: false,
+            max_frame_interval: Duration::from_millis(500),
+        }
+    }
+}
+
 pub trait GuiContext: Sized + Send + Sync {
     type Controller: Controller;
     type Eyes: Eyes;
-    fn from_window_name(name: &str) -> eyre::Result<Self>;
+    fn from_window_name(name: &str, params: &WindowParams) -> eyre::Result<Self>;
     fn controller(&self) -> eyre::Result<Self::Controller>;
     fn eyes(&self) -> eyre::Result<Self::Eyes>;
 }
@@ -26,3 +99,6 @@ pub trait Controller: Sized + Send + Sync {
 pub trait Eyes: Sized + Send + Sync {
     fn run(self, send: SyncSender<ToBrain>) -> eyre::Result<()>;
 }
+pub trait Ears: Sized + Send + Sync {
+    fn run(self, send: SyncSender<ToBrain>) -> eyre::Result<()>;
+}
@@ -0,0 +1,38 @@
+mod pulse;
+mod wav;
+
+pub use pulse::PulseEars;
+pub use wav::WavEars;
+
+use crate::control::{Ears, ToBrain};
+use std::sync::mpsc::SyncSender;
+
+/// Number of samples sent to the brain at once, about 21 ms at 48 kHz.
+pub const BUFFER_LEN: usize = 1024;
+
+/// One of the audio backends, picked at runtime.
+pub enum AnyEars {
+    Wav(WavEars),
+    Pulse(PulseEars),
+}
+impl AnyEars {
+    /// Parses `wav:<path>`, `pulse` or `pulse:<source>`.
+    pub fn from_spec(spec: &str) -> eyre::Result<Self> {
+        match spec.split_once(':') {
+            Some(("wav", path)) => Ok(Self::Wav(WavEars::new(path, true))),
+            Some(("pulse", source)) => Ok(Self::Pulse(PulseEars::new(Some(source.to_owned())))),
+            None if spec == "pulse" => Ok(Self::Pulse(PulseEars::new(None))),
+            _ => {
+                eyre::bail!("Unknown audio source {spec}, expected wav:<path> or pulse[:<source>]")
+            }
+        }
+    }
+}
+impl Ears for AnyEars {
+    fn run(self, send: SyncSender<ToBrain>) -> eyre::Result<()> {
+        match self {
+            AnyEars::Wav(ears) => ears.run(send),
+            AnyEars::Pulse(ears) => ears.run(send),
+        }
+    }
+}
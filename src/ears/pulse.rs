@@ -0,0 +1,80 @@
+use crate::control::{AudioBuffer, Ears, ToBrain};
+
+use super::BUFFER_LEN;
+use eyre::{bail, Context};
+use std::{
+    io::{ErrorKind, Read},
+    process::{Child, Command, Stdio},
+    sync::mpsc::SyncSender,
+    time::Instant,
+};
+
+const SAMPLE_RATE: u32 = 48000;
+
+/// Records what the speakers play from a PulseAudio or PipeWire monitor source.
+pub struct PulseEars {
+    /// The source to record, the monitor of the default sink if unset.
+    source: Option<String>,
+}
+impl PulseEars {
+    pub fn new(source: Option<String>) -> Self {
+        Self { source }
+    }
+    /// Starts a recorder writing raw mono `f32` samples to its stdout.
+    ///
+    /// `parec` works on PulseAudio and on PipeWire through pipewire-pulse, `pw-record` is
+    /// the fallback for PipeWire setups without the Pulse tools.
+    fn spawn(&self) -> eyre::Result<Child> {
+        let rate = SAMPLE_RATE.to_string();
+        let source = self.source.as_deref().unwrap_or("@DEFAULT_MONITOR@");
+        let parec = Command::new("parec")
+            .args(["--format=float32le", "--channels=1", "--raw"])
+            .arg(format!("--rate={rate}"))
+            .arg(format!("--device={source}"))
+            .stdout(Stdio::piped())
+            .spawn();
+        match parec {
+            Err(e) if e.kind() == ErrorKind::NotFound => {}
+            other => return other.wrap_err("Failed to start parec"),
+        }
+        let mut pw_record = Command::new("pw-record");
+        pw_record.args(["--format", "f32", "--channels", "1", "--rate", &rate]);
+        match &self.source {
+            Some(source) => pw_record.args(["--target", source]),
+            None => pw_record.args(["-P", "stream.capture.sink=true"]),
+        };
+        match pw_record.arg("-").stdout(Stdio::piped()).spawn() {
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                bail!("Neither parec nor pw-record is installed")
+            }
+            other => other.wrap_err("Failed to start pw-record"),
+        }
+    }
+}
+impl Ears for PulseEars {
+    fn run(self, send: SyncSender<ToBrain>) -> eyre::Result<()> {
+        let mut child = self.spawn()?;
+        let mut stdout = child.stdout.take().expect("recorder stdout is piped");
+        let mut bytes = vec![0; BUFFER_LEN * 4];
+        let result = loop {
+            if let Err(e) = stdout.read_exact(&mut bytes) {
+                break Err(e).wrap_err("Audio recorder stopped");
+            }
+            let captured = Instant::now();
+            let samples = bytes
+                .chunks_exact(4)
+                .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+                .collect();
+            if let Err(e) = send.send(ToBrain::Audio(AudioBuffer {
+                sample_rate: SAMPLE_RATE,
+                samples,
+                captured,
+            })) {
+                break Err(e.into());
+            }
+        };
+        let _ = child.kill();
+        let _ = child.wait();
+        result
+    }
+}
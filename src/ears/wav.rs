@@ -0,0 +1,68 @@
+use crate::control::{AudioBuffer, Ears, ToBrain};
+
+use super::BUFFER_LEN;
+use eyre::Context;
+use hound::{SampleFormat, WavReader};
+use std::{
+    path::PathBuf,
+    sync::mpsc::SyncSender,
+    thread::sleep,
+    time::{Duration, Instant},
+};
+
+/// Plays a WAV file to the brain, mixed down to mono.
+pub struct WavEars {
+    path: PathBuf,
+    /// Whether to pace the buffers like a live recording instead of sending them all at once.
+    realtime: bool,
+}
+impl WavEars {
+    pub fn new(path: impl Into<PathBuf>, realtime: bool) -> Self {
+        Self {
+            path: path.into(),
+            realtime,
+        }
+    }
+    /// Reads the whole file as mono samples, returning them along with the sample rate.
+    pub fn read(&self) -> eyre::Result<(u32, Vec<f32>)> {
+        let reader = WavReader::open(&self.path)
+            .wrap_err_with(|| format!("Failed to open {}", self.path.display()))?;
+        let spec = reader.spec();
+        let interleaved: Vec<f32> = match spec.sample_format {
+            SampleFormat::Float => reader.into_samples::<f32>().collect::<Result<_, _>>()?,
+            SampleFormat::Int => {
+                let max = (1i64 << (spec.bits_per_sample - 1)) as f32;
+                reader
+                    .into_samples::<i32>()
+                    .map(|s| s.map(|s| s as f32 / max))
+                    .collect::<Result<_, _>>()?
+            }
+        };
+        let channels = spec.channels as usize;
+        let mono = interleaved
+            .chunks_exact(channels)
+            .map(|frame| frame.iter().sum::<f32>() / channels as f32)
+            .collect();
+        Ok((spec.sample_rate, mono))
+    }
+}
+impl Ears for WavEars {
+    fn run(self, send: SyncSender<ToBrain>) -> eyre::Result<()> {
+        let (sample_rate, samples) = self.read()?;
+        let start = Instant::now();
+        for (i, chunk) in samples.chunks(BUFFER_LEN).enumerate() {
+            // A recording can't be heard before its last sample was played
+            let end = i * BUFFER_LEN + chunk.len();
+            let due = Duration::from_secs_f64(end as f64 / sample_rate as f64);
+            if self.realtime {
+                sleep(due.saturating_sub(start.elapsed()));
+            }
+            send.send(ToBrain::Audio(AudioBuffer {
+                sample_rate,
+                samples: chunk.to_vec(),
+                captured: start + due,
+            }))?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,280 @@
+use std::{
+    io,
+    os::fd::RawFd,
+    sync::mpsc::SyncSender,
+    thread,
+    time::{Duration, Instant},
+};
+
+use image::RgbImage;
+
+use crate::{
+    control::{Eyes, ToBrain, WindowEvent, WindowParams},
+    recog::Rect,
+};
+
+/// How often to look for the game window again after it was closed, and to tell the brain
+/// it's still gone.
+const SEARCH_INTERVAL: Duration = Duration::from_secs(1);
+/// How long to wait before capturing again after the server refused to.
+const RETRY_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Size and visibility of the followed window.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Geometry {
+    pub size: [u32; 2],
+    /// Whether the window is mapped, so there is anything to capture.
+    pub viewable: bool,
+}
+
+/// Something the window server reported about the followed window.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Change {
+    /// The contents of the area changed.
+    Damaged(Rect),
+    Unmapped,
+    Mapped,
+    /// The window was moved, resized or restacked, and now has this width and height.
+    Configured([u32; 2]),
+    Destroyed,
+}
+
+/// What capturing the window came to.
+pub enum Capture {
+    Frame(RgbImage),
+    /// The server refused to capture the window this time, e.g. because it's partly off
+    /// screen or went away since its last events.
+    Refused(eyre::Report),
+}
+
+/// A connection to a window server that captures one window and reports what happens to
+/// it, the part of the eyes that differs between backends.
+pub trait WindowSource {
+    type Window: Copy;
+
+    /// The window followed now.
+    fn window(&self) -> Self::Window;
+    /// Finds the window called `name`.
+    fn find(&mut self, name: &str) -> eyre::Result<Self::Window>;
+    /// Starts capturing `window` and listening to its events.
+    fn follow(&mut self, window: Self::Window) -> eyre::Result<Geometry>;
+    /// Reads the geometry of the window again.
+    fn refresh(&mut self) -> eyre::Result<Geometry>;
+    /// The next change of the window that already arrived, skipping other events.
+    fn next_change(&mut self) -> eyre::Result<Option<Change>>;
+    /// Waits until the server has news or `timeout` passed, forever without one. Returns
+    /// whether there is news.
+    fn wait(&mut self, timeout: Option<Duration>) -> eyre::Result<bool>;
+    /// Whether the window's changes get reported as [`Change::Damaged`].
+    fn reports_damage(&self) -> bool;
+    /// Forgets the damage reported so far.
+    fn clear_damage(&mut self) -> eyre::Result<()>;
+    /// Frees the off-screen copy of the window, which is out of date once the window gets
+    /// new storage.
+    fn forget_pixmap(&mut self) -> eyre::Result<()>;
+    /// Captures the window, which is `size` big.
+    fn capture(&mut self, size: [u32; 2]) -> eyre::Result<Capture>;
+}
+
+/// Eyes that follow the game window through `S`, through hiding, resizing and restarts
+/// of the game.
+pub struct WindowEyes<S> {
+    source: S,
+    /// What the window was found by, to find it again.
+    name: String,
+    /// Size of the window, kept up to date by its events.
+    size: [u32; 2],
+    /// Whether the window is mapped, so there is anything to capture.
+    visible: bool,
+    /// Whether the middle third of the window changed since the last frame.
+    damaged: bool,
+    /// Whether the server refused to capture the last frame.
+    refused: bool,
+    max_frame_interval: Duration,
+    last_frame: Option<Instant>,
+}
+
+impl<S: WindowSource> WindowEyes<S> {
+    pub fn new(mut source: S, name: String, params: &WindowParams) -> eyre::Result<Self> {
+        let geometry = source.follow(source.window())?;
+        Ok(Self {
+            source,
+            name,
+            size: geometry.size,
+            visible: geometry.viewable,
+            damaged: true,
+            refused: false,
+            max_frame_interval: params.max_frame_interval,
+            last_frame: None,
+        })
+    }
+
+    /// Starts following `window`, returning whether it's visible.
+    fn follow(&mut self, window: S::Window) -> eyre::Result<bool> {
+        let geometry = self.source.follow(window)?;
+        self.size = geometry.size;
+        self.damaged = true;
+        Ok(geometry.viewable)
+    }
+
+    /// Reads the geometry of the window again, returning whether it's visible.
+    fn refresh(&mut self) -> eyre::Result<bool> {
+        let geometry = self.source.refresh()?;
+        self.size = geometry.size;
+        Ok(geometry.viewable)
+    }
+
+    /// Finds a new window by the name of the closed one, waiting until there is one.
+    ///
+    /// Tells the brain the window is still closed between the searches, which fails once
+    /// the brain is gone.
+    fn find_again(&mut self, send: &SyncSender<ToBrain>) -> eyre::Result<bool> {
+        loop {
+            thread::sleep(SEARCH_INTERVAL);
+            // The new window can be gone again as soon as it's found
+            let name = &self.name;
+            if let Ok(Ok(visible)) = self.source.find(name).map(|w| self.follow(w)) {
+                return Ok(visible);
+            }
+            send.send(ToBrain::Window(WindowEvent::Closed))?;
+        }
+    }
+
+    /// Handles the events of the window that arrived since the last frame, and waits for
+    /// the window to be visible again if it isn't.
+    fn handle_events(&mut self, send: &SyncSender<ToBrain>) -> eyre::Result<()> {
+        loop {
+            let Some(change) = self.source.next_change()? else {
+                if self.visible {
+                    return Ok(());
+                }
+                // Tells the brain the window is still hidden now and then, which fails once
+                // the brain is gone
+                if !self.source.wait(Some(SEARCH_INTERVAL))? {
+                    send.send(ToBrain::Window(WindowEvent::Hidden))?;
+                }
+                continue;
+            };
+            match change {
+                Change::Damaged(area) => {
+                    let [width, height] = self.size;
+                    self.damaged |= area.intersects(&Rect::middle_third(width, height));
+                }
+                Change::Unmapped => {
+                    self.visible = false;
+                    send.send(ToBrain::Window(WindowEvent::Hidden))?;
+                }
+                Change::Mapped => {
+                    self.source.forget_pixmap()?;
+                    // A window destroyed right after mapping it is taken care of by the
+                    // next event
+                    self.visible = self.refresh().unwrap_or(false);
+                    if self.visible {
+                        send.send(ToBrain::Window(WindowEvent::Shown))?;
+                    }
+                }
+                Change::Configured(size) => {
+                    if size != self.size {
+                        self.source.forget_pixmap()?;
+                        self.size = size;
+                        send.send(ToBrain::Window(WindowEvent::Resized(size)))?;
+                    }
+                }
+                Change::Destroyed => {
+                    send.send(ToBrain::Window(WindowEvent::Closed))?;
+                    self.visible = self.find_again(send)?;
+                    send.send(ToBrain::Window(WindowEvent::Found))?;
+                    if !self.visible {
+                        send.send(ToBrain::Window(WindowEvent::Hidden))?;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Waits until the middle third of the window changed or the next frame is due anyway,
+    /// when capturing only after changes.
+    fn wait_for_damage(&mut self, send: &SyncSender<ToBrain>) -> eyre::Result<()> {
+        if !self.source.reports_damage() {
+            return Ok(());
+        }
+        let max = self.max_frame_interval;
+        loop {
+            let waited = self.last_frame.map_or(max, |at| at.elapsed());
+            if self.damaged || waited >= max {
+                break;
+            }
+            // Sleep until the server has news or the frame is due
+            self.source.wait(Some(max - waited))?;
+            self.handle_events(send)?;
+        }
+        // Changes while capturing count towards the next frame, of the window found last
+        self.source.clear_damage()?;
+        self.damaged = false;
+        self.last_frame = Some(Instant::now());
+        Ok(())
+    }
+
+    /// Skips a frame the server refused to capture, which it keeps doing e.g. for as long
+    /// as the window is partly off screen.
+    fn skip(&mut self, error: eyre::Report) -> eyre::Result<()> {
+        if !self.refused {
+            println!("Warning: {error:#}, skipping frames until the window can be captured");
+            self.refused = true;
+        }
+        self.source.wait(Some(RETRY_INTERVAL))?;
+        Ok(())
+    }
+}
+
+impl<S: WindowSource + Send + Sync> Eyes for WindowEyes<S> {
+    fn run(mut self, send: SyncSender<ToBrain>) -> eyre::Result<()> {
+        if !self.visible {
+            send.send(ToBrain::Window(WindowEvent::Hidden))?;
+        }
+        loop {
+            self.handle_events(&send)?;
+            self.wait_for_damage(&send)?;
+            match self.source.capture(self.size) {
+                Ok(Capture::Frame(frame)) => {
+                    if std::mem::take(&mut self.refused) {
+                        println!("Capturing the game window again");
+                    }
+                    send.send(ToBrain::NextFrame(frame))?;
+                }
+                // The window went away since its last events, the next ones tell how
+                Ok(Capture::Refused(_)) | Err(_) if !self.refresh().unwrap_or(false) => {
+                    self.visible = false;
+                }
+                Ok(Capture::Refused(e)) => self.skip(e)?,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+/// Waits until there's something to read from `fd` or `timeout` passed, forever without
+/// one. Returns whether there is.
+pub fn wait_readable(fd: RawFd, timeout: Option<Duration>) -> io::Result<bool> {
+    // Rounded up, so the wait doesn't end just before a frame is due
+    let timeout = timeout.map_or(-1, |t| {
+        t.as_millis().saturating_add(1).min(i32::MAX as u128) as i32
+    });
+    let mut pollfd = libc::pollfd {
+        fd,
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    // SAFETY: poll only reads and writes the one pollfd it's given, which lives until it
+    // returns. The descriptor isn't used beyond asking the kernel about it, one that's no
+    // longer open is reported in `revents` instead.
+    let ready = unsafe { libc::poll(&mut pollfd, 1, timeout) };
+    if ready < 0 {
+        let error = io::Error::last_os_error();
+        // A signal cut the wait short, the caller checks again anyway
+        if error.kind() != io::ErrorKind::Interrupted {
+            return Err(error);
+        }
+    }
+    Ok(ready > 0)
+}
@@ -0,0 +1,100 @@
+pub mod control;
+pub mod ears;
+pub mod profile;
+pub mod recog;
+#[cfg(feature = "windows")]
+mod util;
+
+#[cfg(any(
+    all(feature = "windows", feature = "xserver"),
+    all(feature = "wayland", feature = "xserver"),
+    all(feature = "windows", feature = "wayland"),
+    all(feature = "xcb", feature = "xserver"),
+    all(feature = "xcb", feature = "windows"),
+    all(feature = "xcb", feature = "wayland")
+))]
+compile_error!("multiple window managers are incompatible");
+
+#[cfg(any(feature = "xserver", feature = "xcb"))]
+mod follow;
+#[cfg(any(feature = "xserver", feature = "xcb"))]
+mod pixels;
+#[cfg(feature = "wayland")]
+mod wayland;
+#[cfg(feature = "windows")]
+mod win32;
+#[cfg(any(feature = "xserver", feature = "xcb"))]
+mod window;
+#[cfg(feature = "xcb")]
+mod xcb;
+#[cfg(feature = "xserver")]
+mod xserver;
+
+use control::{Controller, Ears, Eyes, GuiContext, WindowParams};
+use ears::AnyEars;
+use recog::{Brain, Transition};
+use std::{
+    sync::mpsc::{sync_channel, Receiver},
+    thread::{spawn, JoinHandle},
+};
+
+type ResultJoinHandle = JoinHandle<eyre::Result<()>>;
+pub struct Handles {
+    pub brain: ResultJoinHandle,
+    pub eyes: ResultJoinHandle,
+    pub controller: ResultJoinHandle,
+    pub ears: Option<ResultJoinHandle>,
+    pub transitions: Receiver<Transition>,
+}
+
+fn _launch<C: GuiContext + 'static>(
+    window_name: &str,
+    params: &WindowParams,
+    mut brain: Brain,
+    ears: Option<AnyEars>,
+) -> eyre::Result<Handles> {
+    let context = <C as GuiContext>::from_window_name(window_name, params)?;
+    let eyes = context.eyes()?;
+    let controller = context.controller()?;
+    let transitions = brain.observe();
+
+    let (s1, r1) = sync_channel(2);
+    let (s2, r2) = sync_channel(2);
+    let ears = ears.map(|ears| {
+        let s1 = s1.clone();
+        spawn(move || ears.run(s1))
+    });
+    let eyes = spawn(move || eyes.run(s1));
+    let brain = spawn(move || brain.run(r1, s2));
+    let controller = spawn(move || controller.run(r2));
+    Ok(Handles {
+        brain,
+        eyes,
+        controller,
+        ears,
+        transitions,
+    })
+}
+
+pub fn launch(
+    window_name: &str,
+    params: &WindowParams,
+    brain: Brain,
+    ears: Option<AnyEars>,
+) -> eyre::Result<Handles> {
+    #[cfg(feature = "windows")]
+    return _launch::<win32::Win32Context>(window_name, params, brain, ears);
+    #[cfg(feature = "xserver")]
+    return _launch::<xserver::XContext>(window_name, params, brain, ears);
+    #[cfg(feature = "wayland")]
+    return _launch::<wayland::WaylandContext>(window_name, params, brain, ears);
+    #[cfg(feature = "xcb")]
+    return _launch::<xcb::XcbContext>(window_name, params, brain, ears);
+    #[cfg(all(
+        not(feature = "windows"),
+        not(feature = "xserver"),
+        not(feature = "wayland"),
+        not(feature = "xcb")
+    ))]
+    panic!("no features selected")
+}
@@ -1,79 +1,182 @@
-#![feature(let_chains)]
-mod control;
-mod recog;
-mod util;
-
-#[cfg(any(
-    all(feature = "windows", feature = "xserver"),
-    all(feature = "wayland", feature = "xserver"),
-    all(feature = "windows", feature = "wayland")
-))]
-compile_error!("multiple window managers are incompatible");
-
-#[cfg(feature = "wayland")]
-mod wayland;
-#[cfg(feature = "windows")]
-mod win32;
-#[cfg(feature = "xserver")]
-mod xserver;
-
-use control::{Controller, Eyes, GuiContext};
-use recog::Brain;
+use eyre::{bail, ensure, ContextCompat};
+use fischer::{
+    ears::AnyEars,
+    launch,
+    profile::Profile,
+    recog::{
+        self, Brain, ColorDetector, ColorLut, Dataset, DebugMode, DebugOutput, DebugSink, Detector,
+        Report, SceneGenerator, SceneParams,
+    },
+};
 use std::{
-    io::Write,
-    sync::mpsc::sync_channel,
-    thread::{spawn, JoinHandle},
+    io::Write, path::PathBuf, sync::mpsc::RecvTimeoutError, thread::JoinHandle, time::Duration,
 };
 
-type ResultJoinHandle = JoinHandle<eyre::Result<()>>;
-pub struct Handles {
-    pub brain: ResultJoinHandle,
-    pub eyes: ResultJoinHandle,
-    pub controller: ResultJoinHandle,
+/// What to fish with besides the window itself.
+struct FishOptions {
+    /// Which window the game is, see `WindowQuery` for the ways to describe it.
+    window: String,
+    profile: Profile,
+    detector: String,
+    debug: Option<DebugSink>,
+    ears: Option<AnyEars>,
 }
 
-fn _launch<C: GuiContext + 'static>(window_name: &str) -> eyre::Result<Handles> {
-    let context = <C as GuiContext>::from_window_name(window_name)?;
-    let eyes = context.eyes()?;
-    let controller = context.controller()?;
-    let brain = Brain::new();
-
-    let (s1, r1) = sync_channel(2);
-    let (s2, r2) = sync_channel(2);
-    let eyes = spawn(move || eyes.run(s1));
-    let brain = spawn(move || brain.run(r1, s2));
-    let controller = spawn(move || controller.run(r2));
-    Ok(Handles {
-        brain,
-        eyes,
-        controller,
+/// Parses `--window <query>`, `--profile <file>`, `--detector <name>`, `--ears <source>` and
+/// the debug output options, `--debug <dir>` optionally with `--gif` and `--around <n>`.
+///
+/// The window query is an exact title unless it starts with `re:`, `~`, `class:` or `pid:`,
+/// and defaults to `World of Warcraft`.
+fn fish_options(mut args: impl Iterator<Item = String>) -> eyre::Result<FishOptions> {
+    let mut dir = None;
+    let mut gif = false;
+    let mut mode = DebugMode::EveryFrame;
+    let mut ears = None;
+    let mut window = String::from("World of Warcraft");
+    let mut profile = Profile::default();
+    let mut detector = String::from("color");
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--window" => window = args.next().context("--window needs a query")?,
+            "--profile" => {
+                profile = Profile::load(args.next().context("--profile needs a file")?)?;
+            }
+            "--detector" => detector = args.next().context("--detector needs a name")?,
+            "--ears" => {
+                let spec = args.next().context("--ears needs an audio source")?;
+                ears = Some(AnyEars::from_spec(&spec)?);
+            }
+            "--debug" => dir = Some(args.next().context("--debug needs a directory")?),
+            "--gif" => gif = true,
+            "--around" => {
+                let frames = args.next().context("--around needs a frame count")?;
+                let frames = frames.parse()?;
+                mode = DebugMode::AroundEvents {
+                    before: frames,
+                    after: frames,
+                };
+            }
+            _ => bail!("Unknown argument {arg}"),
+        }
+    }
+    let debug = match dir {
+        Some(dir) if gif => Some(DebugSink::new(DebugOutput::Gif(dir.into()), mode)?),
+        Some(dir) => Some(DebugSink::new(DebugOutput::Png(dir.into()), mode)?),
+        None => None,
+    };
+    Ok(FishOptions {
+        window,
+        profile,
+        detector,
+        debug,
+        ears,
     })
 }
 
-pub fn launch(window_name: &str) -> eyre::Result<Handles> {
-    #[cfg(feature = "windows")]
-    return _launch::<win32::Win32Context>(window_name);
-    #[cfg(feature = "xserver")]
-    return _launch::<xserver::XContext>(window_name);
-    #[cfg(feature = "wayland")]
-    return _launch::<wayland::WaylandContext>(window_name);
-    #[cfg(all(
-        not(feature = "windows"),
-        not(feature = "xserver"),
-        not(feature = "wayland")
-    ))]
-    panic!("no features selected")
+/// Builds the detector called `name`, tuned by `profile`.
+///
+/// With the `onnx` feature, `onnx:<model>` loads a learned detector from a model file.
+fn detector(name: &str, profile: &Profile) -> eyre::Result<Box<dyn Detector>> {
+    #[cfg(feature = "onnx")]
+    if let Some(path) = name.strip_prefix("onnx:") {
+        return Ok(Box::new(recog::OnnxDetector::load(path)?));
+    }
+    match name {
+        "color" => Ok(Box::new(ColorDetector::new(
+            ColorLut::new(&profile.thresholds),
+            profile.scale.bobber,
+        ))),
+        _ => bail!("Unknown detector {name}"),
+    }
+}
+
+/// `fischer evaluate [--detector <name>] [--profile <file>] <dataset>...`
+fn evaluate(mut args: impl Iterator<Item = String>) -> eyre::Result<()> {
+    let mut name = String::from("color");
+    let mut profile = Profile::default();
+    let mut dirs = Vec::new();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--detector" => name = args.next().context("--detector needs a name")?,
+            "--profile" => profile = Profile::load(args.next().context("--profile needs a file")?)?,
+            _ => dirs.push(arg),
+        }
+    }
+    ensure!(
+        !dirs.is_empty(),
+        "Usage: fischer evaluate [--detector <name>] [--profile <file>] <dataset>..."
+    );
+    let mut report = Report::default();
+    for dir in dirs {
+        let dataset = Dataset::open(dir)?;
+        let brain = Brain::new(&profile, detector(&name, &profile)?)?;
+        recog::evaluate(&dataset, &profile, brain, &mut report)?;
+    }
+    print!("{report}");
+    Ok(())
+}
+
+/// `fischer synth [--seed <n>] [--count <n>] <dir>`
+///
+/// Renders `count` synthetic casts into numbered datasets inside `dir`.
+fn synth(mut args: impl Iterator<Item = String>) -> eyre::Result<()> {
+    let mut seed = 0;
+    let mut count = 1;
+    let mut dir = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--seed" => seed = args.next().context("--seed needs a number")?.parse()?,
+            "--count" => count = args.next().context("--count needs a number")?.parse()?,
+            _ => dir = Some(PathBuf::from(arg)),
+        }
+    }
+    let dir = dir.context("Usage: fischer synth [--seed <n>] [--count <n>] <dir>")?;
+    for i in 0..count {
+        let generator = SceneGenerator::new(SceneParams::default(), seed + i);
+        let (labels, frames) = generator.generate();
+        Dataset::save(&dir.join(format!("{i:03}")), &labels, &frames)?;
+    }
+    Ok(())
 }
 
 fn main() -> eyre::Result<()> {
-    let handles = launch("World of Warcraft")?;
-    for _ in 0.. {
+    let mut args = std::env::args().skip(1).peekable();
+    match args.peek().map(String::as_str) {
+        Some("evaluate") => return evaluate(args.skip(1)),
+        Some("synth") => return synth(args.skip(1)),
+        _ => {}
+    }
+    let options = fish_options(args)?;
+    let detector = detector(&options.detector, &options.profile)?;
+    let mut brain = Brain::new(&options.profile, detector)?;
+    if let Some(sink) = options.debug {
+        brain = brain.with_debug(sink);
+    }
+    let mut handles = launch(
+        &options.window,
+        &options.profile.window,
+        brain,
+        options.ears,
+    )?;
+    loop {
         if handles.brain.is_finished()
             || handles.controller.is_finished()
             || handles.eyes.is_finished()
         {
             break;
         }
+        // Running out of sound is no reason to stop fishing, the eyes can do without
+        if handles.ears.as_ref().is_some_and(JoinHandle::is_finished) {
+            let ears = handles.ears.take().expect("checked above");
+            if let Err(e) = ears.join().expect("ears thread panicked") {
+                println!("Stopped listening: {e:#}");
+            }
+        }
+        match handles.transitions.recv_timeout(Duration::from_millis(100)) {
+            Ok(transition) => println!("{transition}"),
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
         std::io::stdout().flush()?;
     }
     Ok(())
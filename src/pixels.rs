@@ -0,0 +1,181 @@
+use eyre::{bail, ensure};
+use image::RgbImage;
+use rayon::prelude::*;
+
+/// How the pixels of a captured image are laid out in memory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PixelFormat {
+    /// 16, 24 or 32.
+    pub bits_per_pixel: u32,
+    /// Length of a row including padding, in bytes.
+    pub bytes_per_line: usize,
+    pub red_mask: u32,
+    pub green_mask: u32,
+    pub blue_mask: u32,
+    /// Whether pixels are stored most significant byte first.
+    pub big_endian: bool,
+}
+
+/// Turns the bits of one colour channel into a value from 0 to 255.
+struct Channel {
+    shift: u32,
+    mask: u32,
+    /// The 8 bit value of every channel value, for channels of at most 8 bits.
+    scale: Vec<u8>,
+    /// How far wider channels are shifted down instead.
+    excess: u32,
+}
+impl Channel {
+    fn new(mask: u32) -> Self {
+        let shift = mask.trailing_zeros().min(31);
+        let bits = (mask >> shift).count_ones();
+        let max = (1u32 << bits.min(8)) - 1;
+        let scale = match bits {
+            1..=8 => (0..=max).map(|v| (v * 255 / max) as u8).collect(),
+            _ => Vec::new(),
+        };
+        Self {
+            shift,
+            mask: mask >> shift,
+            scale,
+            excess: bits.saturating_sub(8),
+        }
+    }
+    fn get(&self, pixel: u32) -> u8 {
+        let value = (pixel >> self.shift) & self.mask;
+        if self.excess == 0 {
+            self.scale[value as usize]
+        } else {
+            (value >> self.excess) as u8
+        }
+    }
+}
+
+/// Converts a row of pixels `N` bytes wide.
+fn convert_row<const N: usize>(out: &mut [u8], row: &[u8], big_endian: bool, rgb: &[Channel; 3]) {
+    for (out, pixel) in out.chunks_exact_mut(3).zip(row.chunks_exact(N)) {
+        let value = pixel.iter().enumerate().fold(0u32, |value, (i, &byte)| {
+            let shift = if big_endian { N - 1 - i } else { i };
+            value | (byte as u32) << (8 * shift)
+        });
+        for (out, channel) in out.iter_mut().zip(rgb) {
+            *out = channel.get(value);
+        }
+    }
+}
+
+/// Converts the raw pixels in `data` to an RGB image of `width` by `height` px.
+pub fn to_rgb(
+    format: &PixelFormat,
+    width: u32,
+    height: u32,
+    data: &[u8],
+) -> eyre::Result<RgbImage> {
+    let bytes = match format.bits_per_pixel {
+        16 | 24 | 32 => format.bits_per_pixel as usize / 8,
+        bits => bail!("Unsupported pixel format of {bits} bits per pixel"),
+    };
+    ensure!(
+        format.red_mask != 0 && format.green_mask != 0 && format.blue_mask != 0,
+        "The pixel format has no colour masks, it's probably not a TrueColor visual"
+    );
+    ensure!(
+        format.bytes_per_line >= width as usize * bytes
+            && data.len() >= format.bytes_per_line * height as usize,
+        "{} bytes don't fit a {width}x{height} image of {} bytes per line",
+        data.len(),
+        format.bytes_per_line
+    );
+    let rgb = [format.red_mask, format.green_mask, format.blue_mask].map(Channel::new);
+    let mut image = RgbImage::new(width, height);
+    if width == 0 {
+        return Ok(image);
+    }
+    image
+        .par_chunks_exact_mut(width as usize * 3)
+        .zip(data.par_chunks(format.bytes_per_line))
+        .for_each(|(out, row)| match bytes {
+            2 => convert_row::<2>(out, row, format.big_endian, &rgb),
+            3 => convert_row::<3>(out, row, format.big_endian, &rgb),
+            _ => convert_row::<4>(out, row, format.big_endian, &rgb),
+        });
+    Ok(image)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The usual masks of a format with `red`, `green` and `blue` bits from the top down.
+    fn format(
+        bits_per_pixel: u32,
+        bytes_per_line: usize,
+        [red, green, blue]: [u32; 3],
+    ) -> PixelFormat {
+        let mask = |bits: u32, shift: u32| ((1 << bits) - 1) << shift;
+        PixelFormat {
+            bits_per_pixel,
+            bytes_per_line,
+            red_mask: mask(red, green + blue),
+            green_mask: mask(green, blue),
+            blue_mask: mask(blue, 0),
+            big_endian: false,
+        }
+    }
+
+    #[test]
+    fn converts_565() {
+        // Pure red, pure green, pure blue and a dark grey, least significant byte first
+        let pixels: [u16; 4] = [0xf800, 0x07e0, 0x001f, 0x2104];
+        let data: Vec<u8> = pixels.iter().flat_map(|p| p.to_le_bytes()).collect();
+        let image = to_rgb(&format(16, 8, [5, 6, 5]), 4, 1, &data).unwrap();
+        assert_eq!(
+            image.as_raw(),
+            &[255, 0, 0, 0, 255, 0, 0, 0, 255, 32, 32, 32]
+        );
+    }
+
+    #[test]
+    fn converts_packed_24_bits_with_padded_rows() {
+        // Two rows of two pixels, each row padded to 8 bytes
+        let data = [
+            0x30, 0x20, 0x10, 0x60, 0x50, 0x40, 0xee, 0xee, //
+            0x90, 0x80, 0x70, 0xc0, 0xb0, 0xa0, 0xee, 0xee,
+        ];
+        let image = to_rgb(&format(24, 8, [8, 8, 8]), 2, 2, &data).unwrap();
+        assert_eq!(
+            image.as_raw(),
+            &[0x10, 0x20, 0x30, 0x40, 0x50, 0x60, 0x70, 0x80, 0x90, 0xa0, 0xb0, 0xc0]
+        );
+    }
+
+    #[test]
+    fn converts_32_bits_ignoring_the_spare_byte() {
+        let data = [0x30, 0x20, 0x10, 0xff, 0x03, 0x02, 0x01, 0x00];
+        let image = to_rgb(&format(32, 8, [8, 8, 8]), 2, 1, &data).unwrap();
+        assert_eq!(image.as_raw(), &[0x10, 0x20, 0x30, 0x01, 0x02, 0x03]);
+    }
+
+    #[test]
+    fn converts_most_significant_byte_first() {
+        let data = [0x00, 0x10, 0x20, 0x30, 0xf8, 0x00];
+        let msb_first = PixelFormat {
+            big_endian: true,
+            ..format(32, 4, [8, 8, 8])
+        };
+        let image = to_rgb(&msb_first, 1, 1, &data[..4]).unwrap();
+        assert_eq!(image.as_raw(), &[0x10, 0x20, 0x30]);
+        let msb_first = PixelFormat {
+            big_endian: true,
+            ..format(16, 2, [5, 6, 5])
+        };
+        let image = to_rgb(&msb_first, 1, 1, &data[4..]).unwrap();
+        assert_eq!(image.as_raw(), &[255, 0, 0]);
+    }
+
+    #[test]
+    fn refuses_data_too_short_for_the_image() {
+        let error = to_rgb(&format(32, 8, [8, 8, 8]), 2, 2, &[0; 12]).unwrap_err();
+        assert!(error.to_string().contains("don't fit"), "{error}");
+    }
+}
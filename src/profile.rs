@@ -0,0 +1,58 @@
+use std::{fs, path::Path};
+
+use eyre::Context;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    control::WindowParams,
+    recog::{
+        ConfidencePolicy, FusionParams, HealthParams, LightingParams, LootParams, ScaleParams,
+        ScreenParams, SplashParams, StateTimings, Thresholds, TrackerParams,
+    },
+};
+
+/// Every tunable parameter of the bot, loaded from a JSON file.
+///
+/// Spatial parameters are relative to the frame or the bobber, so the same profile works
+/// on any monitor and render scale. Fields missing from the file keep their defaults.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Profile {
+    /// Colour thresholds used until a lighting regime is picked.
+    pub thresholds: Thresholds,
+    pub lighting: LightingParams,
+    pub scale: ScaleParams,
+    pub confidence: ConfidencePolicy,
+    pub timings: StateTimings,
+    pub tracker: TrackerParams,
+    pub fusion: FusionParams,
+    pub splash: SplashParams,
+    pub loot: LootParams,
+    pub health: HealthParams,
+    /// Game screens to pause or stop on.
+    pub screens: ScreenParams,
+    pub window: WindowParams,
+}
+impl Profile {
+    pub fn load(path: impl AsRef<Path>) -> eyre::Result<Self> {
+        let path = path.as_ref();
+        let json = fs::read_to_string(path)
+            .wrap_err_with(|| format!("Failed to read profile {}", path.display()))?;
+        serde_json::from_str(&json)
+            .wrap_err_with(|| format!("Failed to parse profile {}", path.display()))
+    }
+}
+
+/// (De)serializes a [`Duration`](std::time::Duration) as whole milliseconds.
+pub mod millis {
+    use std::time::Duration;
+
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u64(duration.as_millis() as u64)
+    }
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+        u64::deserialize(deserializer).map(Duration::from_millis)
+    }
+}
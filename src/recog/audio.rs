@@ -0,0 +1,179 @@
+use std::{
+    f64::consts::TAU,
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::control::AudioBuffer;
+
+/// Tuning of the [`SplashDetector`].
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SplashParams {
+    /// Frequency band the splash is loudest in, in Hz.
+    pub band: (f64, f64),
+    /// Number of frequencies sampled across the band.
+    pub bins: usize,
+    /// An onset needs at least this many times the background energy.
+    pub ratio: f64,
+    /// ...and at least this much energy, so noise in near silence doesn't count.
+    pub min_energy: f64,
+    /// How much of the background is replaced by every quiet buffer.
+    pub adaptation: f64,
+    /// How much of the background is replaced by every loud buffer, so a lasting rise of the
+    /// noise becomes the new background instead of splashing forever.
+    pub loud_adaptation: f64,
+    /// Minimum time between two onsets.
+    #[serde(with = "crate::profile::millis")]
+    pub refractory: Duration,
+}
+impl Default for SplashParams {
+    fn default() -> Self {
+        Self {
+            band: (800.0, 3000.0),
+            bins: 12,
+            ratio: 6.0,
+            min_energy: 1e-5,
+            adaptation: 0.05,
+            loud_adaptation: 0.01,
+            refractory: Duration::from_secs(1),
+        }
+    }
+}
+
+/// A sudden rise of energy in the splash band.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Onset {
+    pub energy: f64,
+    /// The energy relative to the background before it.
+    pub ratio: f64,
+}
+
+/// Mean power of `samples` at `bins` frequencies spread over `band`, using the Goertzel
+/// algorithm on Hann-windowed samples.
+pub fn band_energy(samples: &[f32], sample_rate: u32, band: (f64, f64), bins: usize) -> f64 {
+    let n = samples.len();
+    if n == 0 || bins == 0 {
+        return 0.0;
+    }
+    let window = |i: usize| 0.5 - 0.5 * (TAU * i as f64 / n as f64).cos();
+    let total: f64 = (0..bins)
+        .map(|bin| {
+            let freq = band.0 + (band.1 - band.0) * (bin as f64 + 0.5) / bins as f64;
+            let coeff = 2.0 * (TAU * freq / sample_rate as f64).cos();
+            let (mut s1, mut s2) = (0.0, 0.0);
+            for (i, &sample) in samples.iter().enumerate() {
+                let s = sample as f64 * window(i) + coeff * s1 - s2;
+                s2 = s1;
+                s1 = s;
+            }
+            (s1 * s1 + s2 * s2 - coeff * s1 * s2) / (n * n) as f64
+        })
+        .sum();
+    total / bins as f64
+}
+
+/// Listens for the splash the bobber makes when a fish bites.
+///
+/// Keeps a slowly adapting estimate of the background energy in the splash band, and flags
+/// buffers that jump far above it.
+#[derive(Debug, Clone)]
+pub struct SplashDetector {
+    params: SplashParams,
+    background: Option<f64>,
+    last_onset: Option<Instant>,
+}
+impl SplashDetector {
+    pub fn new(params: SplashParams) -> Self {
+        Self {
+            params,
+            background: None,
+            last_onset: None,
+        }
+    }
+    /// Analyses the next buffer.
+    pub fn process(&mut self, buffer: &AudioBuffer) -> Option<Onset> {
+        let now = buffer.captured;
+        let energy = band_energy(
+            &buffer.samples,
+            buffer.sample_rate,
+            self.params.band,
+            self.params.bins,
+        );
+        let Some(background) = self.background else {
+            self.background = Some(energy);
+            return None;
+        };
+        let ratio = energy / background.max(f64::MIN_POSITIVE);
+        let refractory = self
+            .last_onset
+            .is_some_and(|last| now.saturating_duration_since(last) < self.params.refractory);
+        let loud = ratio >= self.params.ratio && energy >= self.params.min_energy;
+        // Loud buffers only seep into the background, the splash shouldn't mask the next one
+        let a = if loud {
+            self.params.loud_adaptation
+        } else {
+            self.params.adaptation
+        };
+        self.background = Some(background * (1.0 - a) + energy * a);
+        if !loud || refractory {
+            return None;
+        }
+        self.last_onset = Some(now);
+        Some(Onset { energy, ratio })
+    }
+}
+impl Default for SplashDetector {
+    fn default() -> Self {
+        Self::new(SplashParams::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: u32 = 44100;
+    const BUFFER_LEN: usize = 1024;
+
+    /// A buffer of a 1.5 kHz tone at `amplitude`, the `index`th after `start`.
+    fn tone(amplitude: f32, index: usize, start: Instant) -> AudioBuffer {
+        let offset = index * BUFFER_LEN;
+        let samples = (offset..offset + BUFFER_LEN)
+            .map(|i| amplitude * (TAU * 1500.0 * i as f64 / SAMPLE_RATE as f64).sin() as f32)
+            .collect();
+        let end = (offset + BUFFER_LEN) as f64 / SAMPLE_RATE as f64;
+        AudioBuffer {
+            sample_rate: SAMPLE_RATE,
+            samples,
+            captured: start + Duration::from_secs_f64(end),
+        }
+    }
+
+    #[test]
+    fn hears_a_splash_over_quiet_water() {
+        let mut detector = SplashDetector::default();
+        let start = Instant::now();
+        let onsets: Vec<_> = (0..100)
+            .map(|i| {
+                let amplitude = if (50..60).contains(&i) { 0.2 } else { 0.005 };
+                detector.process(&tone(amplitude, i, start)).is_some()
+            })
+            .collect();
+        assert_eq!(onsets.iter().position(|&onset| onset), Some(50));
+        assert_eq!(onsets.iter().filter(|&&onset| onset).count(), 1);
+    }
+
+    #[test]
+    fn lasting_noise_becomes_the_background() {
+        let mut detector = SplashDetector::default();
+        let start = Instant::now();
+        detector.process(&tone(0.005, 0, start));
+        // Ten seconds of louder noise, well past a few refractory periods
+        let onsets = (1..430)
+            .filter(|&i| detector.process(&tone(0.2, i, start)).is_some())
+            .count();
+        assert_eq!(onsets, 1);
+    }
+}
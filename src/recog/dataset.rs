@@ -0,0 +1,82 @@
+use std::{
+    fs::{self, File},
+    io::{BufReader, BufWriter},
+    path::{Path, PathBuf},
+};
+
+use eyre::Context;
+use image::RgbImage;
+use serde::{Deserialize, Serialize};
+
+use super::Rect;
+
+/// Name of the file holding the labels inside a dataset directory.
+pub const LABELS_FILE: &str = "labels.json";
+
+/// Ground truth for a single recorded frame.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FrameLabel {
+    /// Path of the frame image, relative to the dataset directory.
+    pub file: PathBuf,
+    /// Where the bobber is, `None` for frames without a bobber.
+    pub bobber: Option<Rect>,
+}
+
+/// The labels of one recorded cast, stored as `labels.json` next to the frames.
+///
+/// The frames start at the moment the cast key was pressed and are spaced `1 / fps` apart.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Labels {
+    pub fps: f64,
+    pub frames: Vec<FrameLabel>,
+    /// Index of the first frame in which the bobber reacts to a bite, if a fish bit at all.
+    pub bite_frame: Option<usize>,
+}
+
+/// A directory of frames along with their [`Labels`].
+#[derive(Debug, Clone)]
+pub struct Dataset {
+    pub dir: PathBuf,
+    pub labels: Labels,
+}
+impl Dataset {
+    pub fn open(dir: impl Into<PathBuf>) -> eyre::Result<Self> {
+        let dir = dir.into();
+        let path = dir.join(LABELS_FILE);
+        let file =
+            File::open(&path).wrap_err_with(|| format!("Failed to open {}", path.display()))?;
+        let labels: Labels = serde_json::from_reader(BufReader::new(file))
+            .wrap_err_with(|| format!("Failed to parse {}", path.display()))?;
+        if let Some(bite) = labels.bite_frame {
+            eyre::ensure!(
+                bite < labels.frames.len(),
+                "Bite frame {bite} is past the last frame in {}",
+                path.display()
+            );
+        }
+        Ok(Self { dir, labels })
+    }
+    /// Writes `frames` and their `labels` into `dir`, which is created if needed.
+    pub fn save(dir: &Path, labels: &Labels, frames: &[RgbImage]) -> eyre::Result<()> {
+        eyre::ensure!(
+            labels.frames.len() == frames.len(),
+            "{} labels for {} frames",
+            labels.frames.len(),
+            frames.len()
+        );
+        fs::create_dir_all(dir).wrap_err_with(|| format!("Failed to create {}", dir.display()))?;
+        for (label, frame) in labels.frames.iter().zip(frames) {
+            frame.save(dir.join(&label.file))?;
+        }
+        let file = File::create(dir.join(LABELS_FILE))?;
+        serde_json::to_writer_pretty(BufWriter::new(file), labels)?;
+        Ok(())
+    }
+    /// Loads the `index`th frame.
+    pub fn frame(&self, index: usize) -> eyre::Result<RgbImage> {
+        let path = self.dir.join(&self.labels.frames[index].file);
+        let image =
+            image::open(&path).wrap_err_with(|| format!("Failed to load {}", path.display()))?;
+        Ok(image.to_rgb8())
+    }
+}
@@ -0,0 +1,279 @@
+use std::{
+    collections::VecDeque,
+    fs::{self, File},
+    io::BufWriter,
+    path::PathBuf,
+    time::Duration,
+};
+
+use eyre::Context;
+use image::{
+    codecs::gif::{GifEncoder, Repeat},
+    Delay, DynamicImage, Frame, GrayImage, Rgb, RgbImage,
+};
+
+use super::{Detection, Rect};
+
+const ROI_COLOR: Rgb<u8> = Rgb([255, 255, 0]);
+const MASK_COLOR: Rgb<u8> = Rgb([255, 0, 255]);
+const BLOB_COLOR: Rgb<u8> = Rgb([0, 255, 0]);
+const CENTROID_COLOR: Rgb<u8> = Rgb([0, 255, 255]);
+const ANCHOR_COLOR: Rgb<u8> = Rgb([255, 255, 255]);
+/// Frames per clip when writing every frame, 10 s at 20 fps. GIFs are only readable once
+/// finished, so a run that gets killed only loses its last clip.
+const CLIP_FRAMES: usize = 200;
+
+/// Which frames end up written out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DebugMode {
+    /// Every frame the brain sees.
+    EveryFrame,
+    /// Only clips around bites and timeouts, with this many frames before and after the event.
+    AroundEvents { before: usize, after: usize },
+}
+
+/// How the annotated frames are stored.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DebugOutput {
+    /// Numbered PNG files in the directory.
+    Png(PathBuf),
+    /// Animated GIFs in the directory, one per stretch of the run or one per event.
+    Gif(PathBuf),
+}
+impl DebugOutput {
+    fn dir(&self) -> &PathBuf {
+        match self {
+            DebugOutput::Png(dir) | DebugOutput::Gif(dir) => dir,
+        }
+    }
+}
+
+/// Draws what the detector saw over a copy of `frame`.
+///
+/// The pixels set in `mask` are tinted, its connected blobs get a bounding box, the `roi`,
+/// the detected centroid and the `anchor` the bite detector compares against are marked.
+pub fn annotate(
+    frame: &RgbImage,
+    roi: Rect,
+    mask: Option<&GrayImage>,
+    detection: Option<&Detection>,
+    anchor: Option<[i32; 2]>,
+) -> RgbImage {
+    let mut out = frame.clone();
+    if let Some(mask) = mask {
+        for (x, y, m) in mask.enumerate_pixels() {
+            if m[0] > 0 {
+                let p = out.get_pixel_mut(x, y);
+                for c in 0..3 {
+                    p[c] = ((p[c] as u16 + MASK_COLOR[c] as u16) / 2) as u8;
+                }
+            }
+        }
+        for blob in blobs(mask, roi) {
+            draw_rect(&mut out, blob, BLOB_COLOR);
+        }
+    }
+    draw_rect(&mut out, roi, ROI_COLOR);
+    if let Some(detection) = detection {
+        draw_cross(&mut out, detection.pixel_pos(), 6, CENTROID_COLOR);
+    }
+    if let Some(anchor) = anchor {
+        draw_cross(&mut out, anchor, 10, ANCHOR_COLOR);
+    }
+    out
+}
+
+/// Bounding boxes of the 4-connected regions of `mask` within `roi`.
+pub fn blobs(mask: &GrayImage, roi: Rect) -> Vec<Rect> {
+    let roi = roi.clamp(mask.width(), mask.height());
+    let mut seen = vec![false; roi.area() as usize];
+    let index = |x: u32, y: u32| ((y - roi.y) * roi.width + (x - roi.x)) as usize;
+    let mut blobs = Vec::new();
+    let mut stack = Vec::new();
+    for y in roi.y..roi.bottom() {
+        for x in roi.x..roi.right() {
+            if seen[index(x, y)] || mask.get_pixel(x, y)[0] == 0 {
+                continue;
+            }
+            seen[index(x, y)] = true;
+            stack.push([x, y]);
+            let (mut min, mut max) = ([x, y], [x, y]);
+            while let Some([px, py]) = stack.pop() {
+                min = [min[0].min(px), min[1].min(py)];
+                max = [max[0].max(px), max[1].max(py)];
+                let neighbours = [
+                    (px > roi.x).then(|| [px - 1, py]),
+                    (px + 1 < roi.right()).then(|| [px + 1, py]),
+                    (py > roi.y).then(|| [px, py - 1]),
+                    (py + 1 < roi.bottom()).then(|| [px, py + 1]),
+                ];
+                for [nx, ny] in neighbours.into_iter().flatten() {
+                    if !seen[index(nx, ny)] && mask.get_pixel(nx, ny)[0] > 0 {
+                        seen[index(nx, ny)] = true;
+                        stack.push([nx, ny]);
+                    }
+                }
+            }
+            blobs.push(Rect::from_corners(min, max));
+        }
+    }
+    blobs
+}
+
+fn draw_rect(img: &mut RgbImage, rect: Rect, color: Rgb<u8>) {
+    let rect = rect.clamp(img.width(), img.height());
+    if rect.area() == 0 {
+        return;
+    }
+    for x in rect.x..rect.right() {
+        img.put_pixel(x, rect.y, color);
+        img.put_pixel(x, rect.bottom() - 1, color);
+    }
+    for y in rect.y..rect.bottom() {
+        img.put_pixel(rect.x, y, color);
+        img.put_pixel(rect.right() - 1, y, color);
+    }
+}
+
+fn draw_cross(img: &mut RgbImage, [cx, cy]: [i32; 2], size: i32, color: Rgb<u8>) {
+    for d in -size..=size {
+        for [x, y] in [[cx + d, cy], [cx, cy + d]] {
+            if x >= 0 && y >= 0 && (x as u32) < img.width() && (y as u32) < img.height() {
+                img.put_pixel(x as u32, y as u32, color);
+            }
+        }
+    }
+}
+
+/// Frames of one clip on their way to disk.
+enum Clip {
+    Png { dir: PathBuf, written: usize },
+    Gif(Box<GifEncoder<BufWriter<File>>>),
+}
+impl Clip {
+    fn create(output: &DebugOutput, name: &str) -> eyre::Result<Self> {
+        Ok(match output {
+            DebugOutput::Png(dir) => {
+                let dir = dir.join(name);
+                fs::create_dir_all(&dir)
+                    .wrap_err_with(|| format!("Failed to create {}", dir.display()))?;
+                Clip::Png { dir, written: 0 }
+            }
+            DebugOutput::Gif(dir) => {
+                let path = dir.join(format!("{name}.gif"));
+                let file = File::create(&path)
+                    .wrap_err_with(|| format!("Failed to create {}", path.display()))?;
+                // Quantizing big frames at the best quality is far too slow to keep up
+                let mut encoder = GifEncoder::new_with_speed(BufWriter::new(file), 20);
+                encoder.set_repeat(Repeat::Infinite)?;
+                Clip::Gif(Box::new(encoder))
+            }
+        })
+    }
+    fn write(&mut self, frame: RgbImage) -> eyre::Result<()> {
+        match self {
+            Clip::Png { dir, written } => {
+                frame.save(dir.join(format!("{written:06}.png")))?;
+                *written += 1;
+            }
+            Clip::Gif(encoder) => {
+                let delay = Delay::from_saturating_duration(Duration::from_millis(50));
+                let rgba = DynamicImage::ImageRgb8(frame).into_rgba8();
+                encoder.encode_frame(Frame::from_parts(rgba, 0, 0, delay))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Writes annotated frames to disk while tuning.
+pub struct DebugSink {
+    output: DebugOutput,
+    mode: DebugMode,
+    /// The most recent frames, waiting for an event to happen.
+    history: VecDeque<RgbImage>,
+    /// The clip being written and how many more frames go into it.
+    clip: Option<(Clip, usize)>,
+    events: usize,
+    /// Number of clips started while writing every frame.
+    parts: usize,
+}
+impl DebugSink {
+    pub fn new(output: DebugOutput, mode: DebugMode) -> eyre::Result<Self> {
+        fs::create_dir_all(output.dir())
+            .wrap_err_with(|| format!("Failed to create {}", output.dir().display()))?;
+        Ok(Self {
+            output,
+            mode,
+            history: VecDeque::new(),
+            clip: None,
+            events: 0,
+            parts: 0,
+        })
+    }
+    /// Records the next annotated frame.
+    pub fn push(&mut self, frame: RgbImage) -> eyre::Result<()> {
+        if self.mode == DebugMode::EveryFrame && self.clip.is_none() {
+            self.parts += 1;
+            let clip = Clip::create(&self.output, &format!("frames-{:04}", self.parts))?;
+            self.clip = Some((clip, CLIP_FRAMES));
+        }
+        if let Some((clip, remaining)) = &mut self.clip {
+            clip.write(frame)?;
+            *remaining -= 1;
+            if *remaining == 0 {
+                self.clip = None;
+            }
+            return Ok(());
+        }
+        if let DebugMode::AroundEvents { before, .. } = self.mode {
+            if self.history.len() == before {
+                self.history.pop_front();
+            }
+            if before > 0 {
+                self.history.push_back(frame);
+            }
+        }
+        Ok(())
+    }
+    /// Marks that `name` just happened, starting a clip around it.
+    pub fn event(&mut self, name: &str) -> eyre::Result<()> {
+        let DebugMode::AroundEvents { after, .. } = self.mode else {
+            return Ok(());
+        };
+        self.events += 1;
+        let mut clip = Clip::create(&self.output, &format!("{:04}-{name}", self.events))?;
+        for frame in self.history.drain(..) {
+            clip.write(frame)?;
+        }
+        // A clip still being written is cut short by the new one
+        self.clip = (after > 0).then_some((clip, after));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::BufReader;
+
+    use image::{codecs::gif::GifDecoder, AnimationDecoder};
+
+    use super::*;
+
+    #[test]
+    fn every_frame_gifs_are_finished_clip_by_clip() {
+        let dir = std::env::temp_dir().join(format!("fischer-debug-{}", std::process::id()));
+        let output = DebugOutput::Gif(dir.clone());
+        let mut sink = DebugSink::new(output, DebugMode::EveryFrame).unwrap();
+        for _ in 0..CLIP_FRAMES + 1 {
+            sink.push(RgbImage::new(4, 4)).unwrap();
+        }
+        // The first clip is complete while the sink is still writing the second
+        let first = BufReader::new(File::open(dir.join("frames-0001.gif")).unwrap());
+        let frames = GifDecoder::new(first).unwrap().into_frames();
+        assert_eq!(frames.collect_frames().unwrap().len(), CLIP_FRAMES);
+        assert!(dir.join("frames-0002.gif").exists());
+        drop(sink);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,255 @@
+use image::{GrayImage, Luma, RgbImage};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use super::{ColorLut, ScaleParams, Thresholds};
+
+/// An axis-aligned rectangle in frame pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct Rect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+impl Rect {
+    pub fn new(x: u32, y: u32, width: u32, height: u32) -> Self {
+        Self {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+    /// The middle third of a `width`x`height` frame, where the bobber is expected to land.
+    pub fn middle_third(width: u32, height: u32) -> Self {
+        let (x0, x1) = (width / 3, (2 * width / 3 + 1).min(width));
+        let (y0, y1) = (height / 3, (2 * height / 3 + 1).min(height));
+        Self::new(x0, y0, x1 - x0, y1 - y0)
+    }
+    /// The smallest rectangle containing both corners, inclusive.
+    pub fn from_corners([x0, y0]: [u32; 2], [x1, y1]: [u32; 2]) -> Self {
+        Self::new(x0, y0, x1 - x0 + 1, y1 - y0 + 1)
+    }
+    pub fn right(&self) -> u32 {
+        self.x + self.width
+    }
+    pub fn bottom(&self) -> u32 {
+        self.y + self.height
+    }
+    pub fn area(&self) -> u32 {
+        self.width * self.height
+    }
+    pub fn center(&self) -> [f64; 2] {
+        [
+            self.x as f64 + self.width as f64 / 2.0,
+            self.y as f64 + self.height as f64 / 2.0,
+        ]
+    }
+    /// Whether the point lies inside the rectangle, grown by `margin` pixels on every side.
+    pub fn contains(&self, [x, y]: [f64; 2], margin: f64) -> bool {
+        x >= self.x as f64 - margin
+            && y >= self.y as f64 - margin
+            && x < self.right() as f64 + margin
+            && y < self.bottom() as f64 + margin
+    }
+    /// Whether the two rectangles share any pixel.
+    pub fn intersects(&self, other: &Rect) -> bool {
+        self.x < other.right()
+            && other.x < self.right()
+            && self.y < other.bottom()
+            && other.y < self.bottom()
+    }
+    /// The rectangle enlarged by `margin` on every side, as far as it doesn't go negative.
+    pub fn grow(&self, margin: u32) -> Self {
+        let x = self.x.saturating_sub(margin);
+        let y = self.y.saturating_sub(margin);
+        Self::new(x, y, self.right() + margin - x, self.bottom() + margin - y)
+    }
+    /// Restricts the rectangle to a `width`x`height` frame.
+    pub fn clamp(&self, width: u32, height: u32) -> Self {
+        let x = self.x.min(width);
+        let y = self.y.min(height);
+        Self::new(
+            x,
+            y,
+            self.right().min(width) - x,
+            self.bottom().min(height) - y,
+        )
+    }
+}
+
+/// A bobber found in a frame.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Detection {
+    /// Sub-pixel position of the bobber.
+    pub pos: [f64; 2],
+    pub bbox: Rect,
+    /// Number of pixels that were attributed to the bobber.
+    pub pixels: u32,
+    /// How sure the detector is that this is the bobber, from 0 to 1.
+    pub confidence: f32,
+    /// Name of the [`Detector`] that produced this.
+    pub detector: &'static str,
+}
+impl Detection {
+    /// The position rounded to whole pixels.
+    pub fn pixel_pos(&self) -> [i32; 2] {
+        self.pos.map(|p| p.round() as i32)
+    }
+}
+
+/// Something that can locate the bobber in a frame.
+pub trait Detector: Send + Sync {
+    fn name(&self) -> &'static str;
+    /// Looks for the bobber inside the `roi` of `frame`.
+    fn detect(&self, frame: &RgbImage, roi: Rect) -> Option<Detection>;
+    /// Switches to the colour thresholds of a new lighting regime, if the detector uses any.
+    fn set_thresholds(&mut self, _thresholds: &Thresholds) {}
+    /// The pixels inside `roi` the detector considers part of the bobber, if it works that way.
+    fn mask(&self, _frame: &RgbImage, _roi: Rect) -> Option<GrayImage> {
+        None
+    }
+}
+
+/// Running totals of the matching pixels in a part of the frame.
+#[derive(Debug, Clone, Copy)]
+struct Moments {
+    total_x: u64,
+    total_y: u64,
+    count: u64,
+    min: [u32; 2],
+    max: [u32; 2],
+}
+impl Moments {
+    const EMPTY: Self = Self {
+        total_x: 0,
+        total_y: 0,
+        count: 0,
+        min: [u32::MAX; 2],
+        max: [0; 2],
+    };
+    fn merge(self, other: Self) -> Self {
+        Self {
+            total_x: self.total_x + other.total_x,
+            total_y: self.total_y + other.total_y,
+            count: self.count + other.count,
+            min: [self.min[0].min(other.min[0]), self.min[1].min(other.min[1])],
+            max: [self.max[0].max(other.max[0]), self.max[1].max(other.max[1])],
+        }
+    }
+}
+
+/// Finds the bobber as the center of mass of the pixels that pass the colour thresholds.
+pub struct ColorDetector {
+    lut: ColorLut,
+    /// Size of a clearly visible bobber, relative to the frame height.
+    bobber: f64,
+}
+impl ColorDetector {
+    pub fn new(lut: ColorLut, bobber: f64) -> Self {
+        Self { lut, bobber }
+    }
+    /// Classifies the rows of `roi` in parallel and sums up the matching pixels.
+    fn moments(&self, frame: &RgbImage, roi: Rect) -> Moments {
+        let w = frame.width() as usize;
+        let roi = roi.clamp(frame.width(), frame.height());
+        if roi.area() == 0 {
+            return Moments::EMPTY;
+        }
+        frame
+            .as_raw()
+            .par_chunks_exact(w * 3)
+            .enumerate()
+            .skip(roi.y as usize)
+            .take(roi.height as usize)
+            .map(|(y, row)| {
+                let mut moments = Moments::EMPTY;
+                for (x, pixel) in row
+                    .chunks_exact(3)
+                    .enumerate()
+                    .take(roi.right() as usize)
+                    .skip(roi.x as usize)
+                {
+                    if self.lut.matches([pixel[0], pixel[1], pixel[2]]) {
+                        moments.total_x += x as u64;
+                        moments.count += 1;
+                        moments.min[0] = moments.min[0].min(x as u32);
+                        moments.max[0] = moments.max[0].max(x as u32);
+                    }
+                }
+                if moments.count > 0 {
+                    moments.total_y = y as u64 * moments.count;
+                    moments.min[1] = y as u32;
+                    moments.max[1] = y as u32;
+                }
+                moments
+            })
+            .reduce(|| Moments::EMPTY, Moments::merge)
+    }
+}
+impl Default for ColorDetector {
+    fn default() -> Self {
+        Self::new(ColorLut::default(), ScaleParams::default().bobber)
+    }
+}
+impl Detector for ColorDetector {
+    fn name(&self) -> &'static str {
+        "color"
+    }
+    fn detect(&self, frame: &RgbImage, roi: Rect) -> Option<Detection> {
+        let moments = self.moments(frame, roi);
+        if moments.count == 0 {
+            return None;
+        }
+        let count = moments.count as f64;
+        let bbox = Rect::from_corners(moments.min, moments.max);
+        // A real bobber is both big enough and compact, scattered noise has a mostly empty bbox
+        let expected = (self.bobber * frame.height() as f64).powi(2);
+        let size = 1.0 - (-count / expected).exp();
+        let fill = (count / bbox.area() as f64 / 0.5).min(1.0);
+        Some(Detection {
+            pos: [
+                moments.total_x as f64 / count,
+                moments.total_y as f64 / count,
+            ],
+            bbox,
+            pixels: moments.count as u32,
+            confidence: (size * fill) as f32,
+            detector: self.name(),
+        })
+    }
+    fn set_thresholds(&mut self, thresholds: &Thresholds) {
+        self.lut = ColorLut::new(thresholds);
+    }
+    fn mask(&self, frame: &RgbImage, roi: Rect) -> Option<GrayImage> {
+        let roi = roi.clamp(frame.width(), frame.height());
+        let mut mask = GrayImage::new(frame.width(), frame.height());
+        for y in roi.y..roi.bottom() {
+            for x in roi.x..roi.right() {
+                if self.lut.matches(frame.get_pixel(x, y).0) {
+                    mask.put_pixel(x, y, Luma([255]));
+                }
+            }
+        }
+        Some(mask)
+    }
+}
+
+/// Minimum detection confidences the brain acts upon.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ConfidencePolicy {
+    /// Detections below this are not even fed to the tracker.
+    pub track: f32,
+    /// A bite is only acted upon if the detection that showed it is at least this confident.
+    pub click: f32,
+}
+impl Default for ConfidencePolicy {
+    fn default() -> Self {
+        Self {
+            track: 0.2,
+            click: 0.5,
+        }
+    }
+}
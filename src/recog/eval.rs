@@ -0,0 +1,231 @@
+use std::{
+    fmt,
+    sync::mpsc::sync_channel,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    control::{ToBrain, ToController},
+    profile::Profile,
+};
+
+use super::{Brain, Dataset, Illumination, Rect};
+
+/// How the bite logic did on one recorded cast.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BiteOutcome {
+    /// Clicked after a labelled bite, `frames` late.
+    Hit { frames: i64, seconds: f64 },
+    /// There was a bite but no click.
+    Missed,
+    /// Clicked before the labelled bite, which would have pulled the hook out of the water.
+    Early,
+    /// Clicked on a cast without a bite.
+    FalseAlarm,
+    /// No bite and no click.
+    Quiet,
+}
+
+/// Detection and bite-timing statistics over any number of datasets.
+#[derive(Debug, Clone, Default)]
+pub struct Report {
+    pub frames: usize,
+    pub frames_with_bobber: usize,
+    pub true_positives: usize,
+    pub false_positives: usize,
+    pub false_negatives: usize,
+    /// Distance of every correct detection from the centre of the labelled box, in px.
+    pub localisation: Vec<f64>,
+    pub bites: Vec<BiteOutcome>,
+}
+impl Report {
+    /// The share of detections that were right, unless there were none.
+    pub fn precision(&self) -> Option<f64> {
+        ratio(
+            self.true_positives,
+            self.true_positives + self.false_positives,
+        )
+    }
+    /// The share of labelled bobbers that were found, unless there were none.
+    pub fn recall(&self) -> Option<f64> {
+        ratio(
+            self.true_positives,
+            self.true_positives + self.false_negatives,
+        )
+    }
+}
+
+fn ratio(part: usize, total: usize) -> Option<f64> {
+    (total > 0).then(|| part as f64 / total as f64)
+}
+
+/// Shows `value` with three decimals, or that there's nothing to show.
+struct Share(Option<f64>);
+impl fmt::Display for Share {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Some(value) => write!(f, "{value:.3}"),
+            None => write!(f, "n/a"),
+        }
+    }
+}
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "frames        {} ({} with bobber)",
+            self.frames, self.frames_with_bobber
+        )?;
+        writeln!(f, "precision     {}", Share(self.precision()))?;
+        writeln!(f, "recall        {}", Share(self.recall()))?;
+        if !self.localisation.is_empty() {
+            let mean = self.localisation.iter().sum::<f64>() / self.localisation.len() as f64;
+            let max = self.localisation.iter().copied().fold(0.0, f64::max);
+            writeln!(f, "localisation  mean {mean:.2} px, max {max:.2} px")?;
+        }
+        let labelled = self
+            .bites
+            .iter()
+            .filter(|b| {
+                matches!(
+                    b,
+                    BiteOutcome::Hit { .. } | BiteOutcome::Missed | BiteOutcome::Early
+                )
+            })
+            .count();
+        let timings: Vec<_> = self
+            .bites
+            .iter()
+            .filter_map(|b| match b {
+                BiteOutcome::Hit { frames, seconds } => Some((*frames, *seconds)),
+                _ => None,
+            })
+            .collect();
+        let false_alarms = self
+            .bites
+            .iter()
+            .filter(|b| matches!(b, BiteOutcome::FalseAlarm | BiteOutcome::Early))
+            .count();
+        writeln!(
+            f,
+            "bites         {}/{labelled} caught, {false_alarms} false alarms",
+            timings.len()
+        )?;
+        if !timings.is_empty() {
+            let n = timings.len() as f64;
+            let mean_frames = timings.iter().map(|t| t.0 as f64).sum::<f64>() / n;
+            let mean_ms = timings.iter().map(|t| t.1).sum::<f64>() / n * 1000.0;
+            let worst = timings.iter().map(|t| t.0).max().unwrap_or(0);
+            writeln!(
+                f,
+                "bite timing   mean {mean_frames:+.2} frames ({mean_ms:+.0} ms), worst {worst} frames"
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Runs the detector of `brain` over every frame of `dataset`, lit the way `profile` says, and replays the frames
+/// through `brain` at the recorded frame rate to see when it clicks, adding the results to `report`.
+pub fn evaluate(
+    dataset: &Dataset,
+    profile: &Profile,
+    mut brain: Brain,
+    report: &mut Report,
+) -> eyre::Result<()> {
+    let labels = &dataset.labels;
+    let (send, recv) = sync_channel(16);
+    let start = Instant::now();
+    brain.start(start, &send)?;
+    let mut click = None;
+    let mut illumination = Illumination::new(profile.lighting.clone(), &profile.thresholds);
+    for (index, label) in labels.frames.iter().enumerate() {
+        let frame = dataset.frame(index)?;
+        let roi = Rect::middle_third(frame.width(), frame.height());
+        let (lighting, regime) = illumination.update(&frame, roi);
+        if let Some(regime) = regime {
+            brain.detector_mut().set_thresholds(&regime.thresholds);
+        }
+        let normalised = illumination.normalise(&frame, roi, lighting);
+        let detection = brain
+            .detector_mut()
+            .detect(normalised.as_ref().unwrap_or(&frame), roi)
+            .filter(|d| d.confidence >= profile.confidence.track);
+        report.frames += 1;
+        match (label.bobber, detection) {
+            (Some(bobber), Some(detection)) if bobber.contains(detection.pos, 0.0) => {
+                let [cx, cy] = bobber.center();
+                report.true_positives += 1;
+                report
+                    .localisation
+                    .push((detection.pos[0] - cx).hypot(detection.pos[1] - cy));
+            }
+            (Some(_), Some(_)) => {
+                report.false_positives += 1;
+                report.false_negatives += 1;
+            }
+            (Some(_), None) => report.false_negatives += 1,
+            (None, Some(_)) => report.false_positives += 1,
+            (None, None) => {}
+        }
+        if label.bobber.is_some() {
+            report.frames_with_bobber += 1;
+        }
+
+        if click.is_none() {
+            let now = start + Duration::from_secs_f64(index as f64 / labels.fps);
+            brain.step(Some(&ToBrain::NextFrame(frame)), now, &send)?;
+            if recv
+                .try_iter()
+                .any(|msg| matches!(msg, ToController::PerformClick(_)))
+            {
+                click = Some(index);
+            }
+        }
+    }
+    report.bites.push(match (labels.bite_frame, click) {
+        (Some(bite), Some(click)) if click < bite => BiteOutcome::Early,
+        (Some(bite), Some(click)) => {
+            let frames = click as i64 - bite as i64;
+            BiteOutcome::Hit {
+                frames,
+                seconds: frames as f64 / labels.fps,
+            }
+        }
+        (Some(_), None) => BiteOutcome::Missed,
+        (None, Some(_)) => BiteOutcome::FalseAlarm,
+        (None, None) => BiteOutcome::Quiet,
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_reports_have_no_precision_or_recall() {
+        let report = Report::default();
+        assert_eq!(report.precision(), None);
+        assert_eq!(report.recall(), None);
+        let text = report.to_string();
+        assert!(text.contains("precision     n/a"), "{text}");
+        assert!(!text.contains("NaN"), "{text}");
+    }
+
+    #[test]
+    fn early_clicks_are_false_alarms() {
+        let report = Report {
+            bites: vec![
+                BiteOutcome::Early,
+                BiteOutcome::Hit {
+                    frames: 2,
+                    seconds: 0.1,
+                },
+            ],
+            ..Report::default()
+        };
+        let text = report.to_string();
+        assert!(text.contains("1/2 caught, 1 false alarms"), "{text}");
+    }
+}
@@ -0,0 +1,315 @@
+use std::{
+    collections::VecDeque,
+    fmt,
+    time::{Duration, Instant},
+};
+
+use image::{imageops, RgbImage};
+use serde::{Deserialize, Serialize};
+
+use super::Rect;
+
+/// A signal that may indicate a bite.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Cue {
+    /// Distance of the bobber from where it settled, in bobber sizes.
+    Displacement,
+    /// Change of the pixels around the bobber from the previous frame, relative to usual.
+    FrameDifference,
+    /// The tracker's normalized innovation, in standard deviations.
+    TrackerResidual,
+    /// Energy of a splash sound relative to the background.
+    Splash,
+}
+impl Cue {
+    pub const ALL: [Cue; 4] = [
+        Cue::Displacement,
+        Cue::FrameDifference,
+        Cue::TrackerResidual,
+        Cue::Splash,
+    ];
+}
+
+/// How much one [`Cue`] counts.
+///
+/// Fields a profile leaves out are 1, not the defaults of the cue in [`FusionParams`].
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CueParams {
+    pub weight: f64,
+    /// The measurement at which the cue is fully active.
+    pub scale: f64,
+}
+impl Default for CueParams {
+    fn default() -> Self {
+        Self {
+            weight: 1.0,
+            scale: 1.0,
+        }
+    }
+}
+
+/// Tuning of the [`BiteFusion`].
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FusionParams {
+    pub displacement: CueParams,
+    pub frame_difference: CueParams,
+    pub tracker_residual: CueParams,
+    pub splash: CueParams,
+    /// Cues only agree if they fire within this long of each other.
+    #[serde(with = "crate::profile::millis")]
+    pub window: Duration,
+    /// The weighted sum of activations a bite needs.
+    pub threshold: f64,
+    /// A cue agrees if it's at least this active.
+    pub agreement: f64,
+    /// Number of cues that have to agree on a bite.
+    pub min_cues: usize,
+}
+impl FusionParams {
+    pub fn cue(&self, cue: Cue) -> CueParams {
+        match cue {
+            Cue::Displacement => self.displacement,
+            Cue::FrameDifference => self.frame_difference,
+            Cue::TrackerResidual => self.tracker_residual,
+            Cue::Splash => self.splash,
+        }
+    }
+}
+impl Default for FusionParams {
+    fn default() -> Self {
+        Self {
+            displacement: CueParams {
+                weight: 1.0,
+                scale: 0.3,
+            },
+            frame_difference: CueParams {
+                weight: 0.5,
+                scale: 4.0,
+            },
+            tracker_residual: CueParams {
+                weight: 0.5,
+                scale: 4.0,
+            },
+            splash: CueParams {
+                weight: 1.0,
+                scale: 6.0,
+            },
+            window: Duration::from_millis(300),
+            threshold: 1.4,
+            agreement: 0.5,
+            min_cues: 2,
+        }
+    }
+}
+
+/// What one cue added to a [`Decision`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Contribution {
+    pub cue: Cue,
+    /// The strongest measurement of the cue within the window.
+    pub value: f64,
+    /// `value` relative to the cue's scale, capped at 1.
+    pub activation: f64,
+    /// `activation` times the cue's weight.
+    pub weighted: f64,
+}
+
+/// The fused verdict on whether a fish bit.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Decision {
+    /// Whether the cues add up to a bite.
+    pub bite: bool,
+    pub score: f64,
+    pub agreeing: usize,
+    pub contributions: Vec<Contribution>,
+}
+impl fmt::Display for Decision {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "score {:.2} from {} agreeing cues:",
+            self.score, self.agreeing
+        )?;
+        for c in &self.contributions {
+            write!(
+                f,
+                " {:?} {:.2} ({:.2} -> {:.2})",
+                c.cue, c.value, c.activation, c.weighted
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Combines the bite cues into a single decision.
+///
+/// Every cue is scaled into an activation and weighted. A bite is decided once the weighted
+/// activations seen within the window add up to the threshold and enough cues agree.
+#[derive(Debug, Clone)]
+pub struct BiteFusion {
+    params: FusionParams,
+    observations: VecDeque<(Cue, f64, Instant)>,
+}
+impl BiteFusion {
+    pub fn new(params: FusionParams) -> Self {
+        Self {
+            params,
+            observations: VecDeque::new(),
+        }
+    }
+    /// Forgets everything observed so far.
+    pub fn reset(&mut self) {
+        self.observations.clear();
+    }
+    /// Records that `cue` measured `value` at `now`.
+    pub fn observe(&mut self, cue: Cue, value: f64, now: Instant) {
+        self.observations.push_back((cue, value, now));
+    }
+    /// The contribution of every cue observed within the window before `now`.
+    pub fn contributions(&mut self, now: Instant) -> Vec<Contribution> {
+        let window = self.params.window;
+        self.observations
+            .retain(|(_, _, at)| now.saturating_duration_since(*at) <= window);
+        Cue::ALL
+            .into_iter()
+            .filter_map(|cue| {
+                let value = self
+                    .observations
+                    .iter()
+                    .filter(|(c, _, _)| *c == cue)
+                    .map(|(_, value, _)| *value)
+                    .reduce(f64::max)?;
+                let params = self.params.cue(cue);
+                let activation = (value / params.scale).clamp(0.0, 1.0);
+                Some(Contribution {
+                    cue,
+                    value,
+                    activation,
+                    weighted: activation * params.weight,
+                })
+            })
+            .collect()
+    }
+    /// Decides whether the cues observed up to `now` amount to a bite.
+    ///
+    /// A positive decision clears the observations, so a bite is only reported once.
+    pub fn decide(&mut self, now: Instant) -> Decision {
+        let contributions = self.contributions(now);
+        let score = contributions.iter().map(|c| c.weighted).sum();
+        let agreeing = contributions
+            .iter()
+            .filter(|c| c.activation >= self.params.agreement)
+            .count();
+        let bite = score >= self.params.threshold && agreeing >= self.params.min_cues;
+        if bite {
+            self.reset();
+        }
+        Decision {
+            bite,
+            score,
+            agreeing,
+            contributions,
+        }
+    }
+}
+
+/// Measures how much the pixels around the bobber change between frames, relative to how
+/// much they usually change while the water is calm.
+#[derive(Debug, Clone, Default)]
+pub struct FrameDifference {
+    /// The area watched in the previous frame, and what was in it.
+    previous: Option<(Rect, RgbImage)>,
+    baseline: Option<f64>,
+}
+impl FrameDifference {
+    /// How quickly the baseline follows the normal motion of the water.
+    const ADAPTATION: f64 = 0.1;
+
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+    /// The mean absolute difference within `area` from the previous frame, divided by the
+    /// baseline. Returns `None` until there's a frame and a baseline to compare against.
+    ///
+    /// Only the watched area is kept between frames, so `area` should stay put.
+    pub fn measure(&mut self, frame: &RgbImage, area: Rect) -> Option<f64> {
+        let area = area.clamp(frame.width(), frame.height());
+        if area.area() == 0 {
+            return None;
+        }
+        let crop = imageops::crop_imm(frame, area.x, area.y, area.width, area.height).to_image();
+        let (previous_area, previous) = self.previous.replace((area, crop.clone()))?;
+        if previous_area != area {
+            return None;
+        }
+        let total: u64 = previous
+            .as_raw()
+            .iter()
+            .zip(crop.as_raw())
+            .map(|(a, b)| a.abs_diff(*b) as u64)
+            .sum();
+        let difference = total as f64 / previous.as_raw().len() as f64;
+        let Some(baseline) = self.baseline else {
+            self.baseline = Some(difference.max(1.0));
+            return None;
+        };
+        let ratio = difference / baseline;
+        // Bites stay out of the baseline, it should only learn the calm water
+        if ratio < 2.0 {
+            self.baseline = Some(
+                (baseline * (1.0 - Self::ADAPTATION) + difference * Self::ADAPTATION).max(1.0),
+            );
+        }
+        Some(ratio)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn profiles_may_leave_out_parts_of_a_cue() {
+        let params: FusionParams =
+            serde_json::from_str(r#"{"splash": {"weight": 2.0}, "threshold": 2.0}"#).unwrap();
+        assert_eq!(
+            params.splash,
+            CueParams {
+                weight: 2.0,
+                scale: 1.0
+            }
+        );
+        assert_eq!(params.displacement, FusionParams::default().displacement);
+        assert_eq!(params.threshold, 2.0);
+    }
+
+    #[test]
+    fn rejected_decisions_tell_what_the_cues_added() {
+        let mut fusion = BiteFusion::new(FusionParams::default());
+        let now = Instant::now();
+        // A strong splash alone isn't enough, it needs a second cue to agree
+        fusion.observe(Cue::Splash, 12.0, now);
+        let decision = fusion.decide(now);
+        assert!(!decision.bite);
+        assert_eq!(decision.agreeing, 1);
+        assert_eq!(decision.contributions.len(), 1);
+        assert_eq!(decision.contributions[0].cue, Cue::Splash);
+        assert_eq!(decision.contributions[0].weighted, 1.0);
+    }
+
+    #[test]
+    fn a_bite_is_decided_once() {
+        let mut fusion = BiteFusion::new(FusionParams::default());
+        let now = Instant::now();
+        fusion.observe(Cue::Splash, 12.0, now);
+        fusion.observe(Cue::Displacement, 0.6, now);
+        let decision = fusion.decide(now);
+        assert!(decision.bite, "{decision}");
+        assert_eq!(decision.agreeing, 2);
+        let after = fusion.decide(now + Duration::from_millis(50));
+        assert!(!after.bite);
+        assert!(after.contributions.is_empty());
+    }
+}
@@ -0,0 +1,238 @@
+use std::fmt;
+
+use image::RgbImage;
+use serde::{Deserialize, Serialize};
+
+/// Number of pixels sampled across the width and height of every frame.
+const GRID: [u32; 2] = [64, 36];
+
+/// What's wrong with the captured frames.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameProblem {
+    /// The same frame keeps arriving, the window is probably minimised or capture is stuck.
+    Frozen,
+    /// The frame is all one colour, like a black window or a loading screen.
+    Uniform,
+    /// The whole scene changed at once, like a loading screen or another window on top.
+    SceneChange,
+}
+impl fmt::Display for FrameProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FrameProblem::Frozen => write!(f, "frames are frozen"),
+            FrameProblem::Uniform => write!(f, "frames are blank"),
+            FrameProblem::SceneChange => write!(f, "the whole scene changed"),
+        }
+    }
+}
+
+/// Whether capture just broke or recovered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HealthChange {
+    Unhealthy(FrameProblem),
+    Healthy,
+}
+
+/// Tuning of the [`FrameHealth`] checks.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HealthParams {
+    /// Identical frames in a row that count as frozen.
+    pub frozen_frames: u32,
+    /// Frames whose brightness varies less than this are blank, in standard deviations
+    /// from 0 to 255.
+    pub uniform_deviation: f64,
+    /// Mean change from one frame to the next that counts as a new scene, from 0 to 255.
+    pub scene_change: f64,
+    /// Healthy frames in a row needed before capture counts as recovered.
+    pub healthy_frames: u32,
+}
+impl Default for HealthParams {
+    fn default() -> Self {
+        Self {
+            frozen_frames: 20,
+            uniform_deviation: 3.0,
+            scene_change: 60.0,
+            healthy_frames: 10,
+        }
+    }
+}
+
+/// Watches the captured frames for signs that they don't show the game.
+///
+/// Every frame is reduced to a coarse grid of brightness samples, which is enough to tell
+/// stale, blank and suddenly different frames apart from the moving water.
+#[derive(Debug, Clone)]
+pub struct FrameHealth {
+    params: HealthParams,
+    previous: Option<Vec<u8>>,
+    identical: u32,
+    healthy: u32,
+    problem: Option<FrameProblem>,
+}
+impl FrameHealth {
+    pub fn new(params: HealthParams) -> Self {
+        Self {
+            params,
+            previous: None,
+            identical: 0,
+            healthy: 0,
+            problem: None,
+        }
+    }
+    /// What's currently wrong with capture, if anything.
+    pub fn problem(&self) -> Option<FrameProblem> {
+        self.problem
+    }
+    fn samples(frame: &RgbImage) -> Vec<u8> {
+        let (w, h) = frame.dimensions();
+        let [gw, gh] = GRID.map(|n| n.min(w.min(h)).max(1));
+        (0..gh)
+            .flat_map(|gy| (0..gw).map(move |gx| (gx, gy)))
+            .map(|(gx, gy)| {
+                let x = (2 * gx + 1) * w / (2 * gw);
+                let y = (2 * gy + 1) * h / (2 * gh);
+                let [r, g, b] = frame.get_pixel(x, y).0.map(u32::from);
+                ((r * 299 + g * 587 + b * 114) / 1000) as u8
+            })
+            .collect()
+    }
+    /// The problem `samples` shows on its own or compared to the previous frame.
+    fn check(&mut self, samples: &[u8]) -> Option<FrameProblem> {
+        let n = samples.len().max(1) as f64;
+        let mean = samples.iter().map(|&s| s as f64).sum::<f64>() / n;
+        let variance = samples
+            .iter()
+            .map(|&s| (s as f64 - mean).powi(2))
+            .sum::<f64>()
+            / n;
+        let previous = self
+            .previous
+            .as_deref()
+            .filter(|p| p.len() == samples.len());
+        self.identical = match previous {
+            Some(previous) if previous == samples => self.identical + 1,
+            _ => 0,
+        };
+        let change = previous.map(|previous| {
+            previous
+                .iter()
+                .zip(samples)
+                .map(|(a, b)| a.abs_diff(*b) as f64)
+                .sum::<f64>()
+                / n
+        });
+        if variance.sqrt() < self.params.uniform_deviation {
+            Some(FrameProblem::Uniform)
+        } else if self.identical >= self.params.frozen_frames {
+            Some(FrameProblem::Frozen)
+        } else if change.is_some_and(|c| c >= self.params.scene_change) {
+            Some(FrameProblem::SceneChange)
+        } else {
+            None
+        }
+    }
+    /// Checks the next frame, returning whether capture just broke or recovered.
+    pub fn update(&mut self, frame: &RgbImage) -> Option<HealthChange> {
+        let samples = Self::samples(frame);
+        let problem = self.check(&samples);
+        self.previous = Some(samples);
+        match (problem, self.problem) {
+            (Some(problem), None) => {
+                self.healthy = 0;
+                self.problem = Some(problem);
+                Some(HealthChange::Unhealthy(problem))
+            }
+            (Some(problem), Some(_)) => {
+                self.healthy = 0;
+                self.problem = Some(problem);
+                None
+            }
+            (None, Some(_)) => {
+                self.healthy += 1;
+                if self.healthy < self.params.healthy_frames {
+                    return None;
+                }
+                self.problem = None;
+                Some(HealthChange::Healthy)
+            }
+            (None, None) => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::Rgb;
+
+    use super::*;
+
+    /// Rippling water around the brightness `base`, different for every `index`.
+    fn water(base: u8, index: u32) -> RgbImage {
+        RgbImage::from_fn(320, 180, |x, y| {
+            let v = base + ((x * 7 + y * 13 + index * 5) % 40) as u8;
+            Rgb([v, v, v])
+        })
+    }
+
+    #[test]
+    fn blank_frames_are_uniform() {
+        let mut health = FrameHealth::new(HealthParams::default());
+        let black = RgbImage::new(320, 180);
+        let change = health.update(&black);
+        assert_eq!(change, Some(HealthChange::Unhealthy(FrameProblem::Uniform)));
+        assert_eq!(health.problem(), Some(FrameProblem::Uniform));
+    }
+
+    #[test]
+    fn repeated_frames_are_frozen() {
+        let params = HealthParams::default();
+        let mut health = FrameHealth::new(params);
+        let frame = water(40, 0);
+        // The first frame and all but the last repeat are fine
+        for _ in 0..params.frozen_frames {
+            assert_eq!(health.update(&frame), None);
+        }
+        let change = health.update(&frame);
+        assert_eq!(change, Some(HealthChange::Unhealthy(FrameProblem::Frozen)));
+    }
+
+    #[test]
+    fn a_jump_in_brightness_is_a_scene_change() {
+        let mut health = FrameHealth::new(HealthParams::default());
+        for index in 0..5 {
+            assert_eq!(health.update(&water(40, index)), None);
+        }
+        let change = health.update(&water(140, 5));
+        assert_eq!(
+            change,
+            Some(HealthChange::Unhealthy(FrameProblem::SceneChange))
+        );
+    }
+
+    #[test]
+    fn recovery_needs_enough_good_frames_in_a_row() {
+        let params = HealthParams::default();
+        let mut health = FrameHealth::new(params);
+        let blank = RgbImage::from_pixel(320, 180, Rgb([60, 60, 60]));
+        assert!(health.update(&blank).is_some());
+        let mut index = 0;
+        for _ in 1..params.healthy_frames {
+            index += 1;
+            assert_eq!(health.update(&water(40, index)), None);
+        }
+        // One bad frame just before recovering starts the count over
+        assert_eq!(health.update(&blank), None);
+        for _ in 1..params.healthy_frames {
+            index += 1;
+            assert_eq!(health.update(&water(40, index)), None);
+            assert_eq!(health.problem(), Some(FrameProblem::Uniform));
+        }
+        index += 1;
+        assert_eq!(
+            health.update(&water(40, index)),
+            Some(HealthChange::Healthy)
+        );
+        assert_eq!(health.problem(), None);
+    }
+}
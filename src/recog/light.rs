@@ -0,0 +1,246 @@
+use image::RgbImage;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use super::{Rect, Thresholds};
+
+/// Colour thresholds for scenes up to a certain brightness.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Regime {
+    pub name: String,
+    /// The regime applies to scenes at most this bright, from 0 to 1.
+    pub max_brightness: f64,
+    pub thresholds: Thresholds,
+}
+
+/// Tuning of the [`Illumination`] stage.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LightingParams {
+    /// Whether frames are white balanced and brightened before detection.
+    pub normalise: bool,
+    /// The mean brightness normalised frames are scaled to.
+    pub target_brightness: f64,
+    /// How much of the brightness estimate is replaced by every frame.
+    pub adaptation: f64,
+    /// How far the brightness has to leave a regime before switching to another one.
+    pub hysteresis: f64,
+    /// Parameter sets to pick from by brightness, darkest first.
+    ///
+    /// Without them, the thresholds of the profile are used by day and relaxed for dusk
+    /// and night.
+    pub regimes: Option<Vec<Regime>>,
+}
+impl LightingParams {
+    /// The regimes to pick from, derived from the `base` thresholds unless configured.
+    pub fn regimes(&self, base: &Thresholds) -> Vec<Regime> {
+        if let Some(regimes) = &self.regimes {
+            return regimes.clone();
+        }
+        // Colours fade in the dark, so the bobber is less saturated than by day
+        let regime = |name: &str, max_brightness, saturation: f64| Regime {
+            name: name.into(),
+            max_brightness,
+            thresholds: Thresholds {
+                saturation_min: base.saturation_min * saturation,
+                ..*base
+            },
+        };
+        vec![
+            regime("night", 0.12, 0.75),
+            regime("dusk", 0.3, 0.875),
+            regime("day", 1.0, 1.0),
+        ]
+    }
+}
+impl Default for LightingParams {
+    fn default() -> Self {
+        Self {
+            normalise: false,
+            target_brightness: 0.45,
+            adaptation: 0.1,
+            hysteresis: 0.03,
+            regimes: None,
+        }
+    }
+}
+
+/// Brightness and colour cast of a part of the frame.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Lighting {
+    /// Mean luma, from 0 to 1.
+    pub brightness: f64,
+    /// Per channel factors that make the mean colour grey.
+    pub gains: [f64; 3],
+}
+impl Lighting {
+    /// Measures the lighting within `roi` of `frame`, assuming the scene averages out to grey.
+    pub fn estimate(frame: &RgbImage, roi: Rect) -> Self {
+        let w = frame.width() as usize;
+        if w == 0 {
+            return Self {
+                brightness: 0.0,
+                gains: [1.0; 3],
+            };
+        }
+        let roi = roi.clamp(frame.width(), frame.height());
+        let totals = frame
+            .as_raw()
+            .par_chunks_exact(w * 3)
+            .skip(roi.y as usize)
+            .take(roi.height as usize)
+            .map(|row| {
+                let mut totals = [0u64; 3];
+                for pixel in row[roi.x as usize * 3..roi.right() as usize * 3].chunks_exact(3) {
+                    for (total, value) in totals.iter_mut().zip(pixel) {
+                        *total += *value as u64;
+                    }
+                }
+                totals
+            })
+            .reduce(|| [0; 3], |a, b| [a[0] + b[0], a[1] + b[1], a[2] + b[2]]);
+        let count = roi.area().max(1) as f64;
+        let [r, g, b] = totals.map(|t| t as f64 / count / 255.0);
+        let grey = (r + g + b) / 3.0;
+        Self {
+            brightness: 0.299 * r + 0.587 * g + 0.114 * b,
+            // A tint this strong is the scene's own colour rather than the light's
+            gains: [r, g, b].map(|c| (grey / c.max(1e-3)).clamp(0.5, 2.0)),
+        }
+    }
+}
+
+/// Adapts detection to the lighting of the scene.
+///
+/// Follows the brightness within the ROI to pick the matching [`Regime`] from the profile,
+/// and optionally white balances and brightens the frame before the detector sees it.
+#[derive(Debug, Clone)]
+pub struct Illumination {
+    params: LightingParams,
+    regimes: Vec<Regime>,
+    brightness: Option<f64>,
+    regime: Option<usize>,
+}
+impl Illumination {
+    /// Follows the lighting with the regimes of `params`, or ones derived from `base`.
+    pub fn new(params: LightingParams, base: &Thresholds) -> Self {
+        Self {
+            regimes: params.regimes(base),
+            params,
+            brightness: None,
+            regime: None,
+        }
+    }
+    /// The smoothed brightness the regime is picked by, from 0 to 1.
+    pub fn brightness(&self) -> Option<f64> {
+        self.brightness
+    }
+    /// Estimates the lighting within `roi` of `frame`, returning it along with the regime
+    /// to switch to if the brightness left the current one.
+    pub fn update(&mut self, frame: &RgbImage, roi: Rect) -> (Lighting, Option<&Regime>) {
+        let lighting = Lighting::estimate(frame, roi);
+        let a = self.params.adaptation;
+        let brightness = match self.brightness {
+            Some(b) => b * (1.0 - a) + lighting.brightness * a,
+            None => lighting.brightness,
+        };
+        self.brightness = Some(brightness);
+        let Some(index) = self.select(brightness) else {
+            return (lighting, None);
+        };
+        self.regime = Some(index);
+        (lighting, self.regimes.get(index))
+    }
+    /// The index of the regime to switch to at `brightness`, if it's not the current one.
+    fn select(&self, brightness: f64) -> Option<usize> {
+        let regimes = &self.regimes;
+        let index = regimes
+            .iter()
+            .position(|r| brightness <= r.max_brightness)
+            .or(regimes.len().checked_sub(1))?;
+        let Some(current) = self.regime else {
+            return Some(index);
+        };
+        if index == current {
+            return None;
+        }
+        // Only switch once clearly outside the current regime, so it doesn't flicker at the edge
+        let min = current
+            .checked_sub(1)
+            .map_or(f64::NEG_INFINITY, |i| regimes[i].max_brightness);
+        let max = regimes[current].max_brightness;
+        let h = self.params.hysteresis;
+        (brightness < min - h || brightness > max + h).then_some(index)
+    }
+    /// A copy of `frame` with the `roi` white balanced and scaled to the target brightness,
+    /// if normalising is enabled.
+    pub fn normalise(&self, frame: &RgbImage, roi: Rect, lighting: Lighting) -> Option<RgbImage> {
+        if !self.params.normalise || frame.width() == 0 {
+            return None;
+        }
+        let gain = self.params.target_brightness / lighting.brightness.max(1e-3);
+        let gains = lighting.gains.map(|g| (g * gain) as f32);
+        let w = frame.width() as usize;
+        let roi = roi.clamp(frame.width(), frame.height());
+        let mut out = frame.clone();
+        out.par_chunks_exact_mut(w * 3)
+            .skip(roi.y as usize)
+            .take(roi.height as usize)
+            .for_each(|row| {
+                for pixel in row[roi.x as usize * 3..roi.right() as usize * 3].chunks_exact_mut(3) {
+                    for (value, gain) in pixel.iter_mut().zip(gains) {
+                        *value = (*value as f32 * gain).min(255.0) as u8;
+                    }
+                }
+            });
+        Some(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::Rgb;
+
+    use super::*;
+
+    #[test]
+    fn default_regimes_keep_the_profile_thresholds_by_day() {
+        let base = Thresholds {
+            cyan_max: 0.2,
+            saturation_min: 0.6,
+        };
+        let mut illumination = Illumination::new(LightingParams::default(), &base);
+        let day = RgbImage::from_pixel(30, 30, Rgb([200, 190, 180]));
+        let roi = Rect::new(0, 0, 30, 30);
+        let (_, regime) = illumination.update(&day, roi);
+        assert_eq!(
+            regime.map(|r| (r.name.as_str(), r.thresholds)),
+            Some(("day", base))
+        );
+        // Darker regimes only relax the saturation
+        let night = &LightingParams::default().regimes(&base)[0];
+        assert_eq!(night.thresholds.cyan_max, base.cyan_max);
+        assert!(night.thresholds.saturation_min < base.saturation_min);
+    }
+
+    #[test]
+    fn configured_regimes_replace_the_defaults() {
+        let regime = Regime {
+            name: "cave".into(),
+            max_brightness: 1.0,
+            thresholds: Thresholds::default(),
+        };
+        let params = LightingParams {
+            regimes: Some(vec![regime.clone()]),
+            ..LightingParams::default()
+        };
+        assert_eq!(params.regimes(&Thresholds::default()), [regime]);
+    }
+
+    #[test]
+    fn empty_frames_have_no_light() {
+        let frame = RgbImage::new(0, 0);
+        let lighting = Lighting::estimate(&frame, Rect::new(0, 0, 10, 10));
+        assert_eq!(lighting.brightness, 0.0);
+    }
+}
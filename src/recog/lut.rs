@@ -0,0 +1,162 @@
+use image::Rgb;
+use serde::{Deserialize, Serialize};
+
+/// Bits kept per colour channel when quantizing a pixel for the lookup.
+const BITS: u32 = 5;
+const LEVELS: usize = 1 << BITS;
+
+/// The channel value in the middle of the quantization cell `q`.
+fn centre(q: usize) -> u8 {
+    ((q << (8 - BITS)) | (1 << (7 - BITS))) as u8
+}
+
+/// Convert RGB color to CMYK color space.
+fn rgb_to_cmyk(rgb: Rgb<u8>) -> (f64, f64, f64, f64) {
+    let r = rgb[0] as f64 / 255.0;
+    let g = rgb[1] as f64 / 255.0;
+    let b = rgb[2] as f64 / 255.0;
+
+    let k = 1.0 - r.max(g).max(b);
+    let c = (1.0 - r - k) / (1.0 - k);
+    let m = (1.0 - g - k) / (1.0 - k);
+    let y = (1.0 - b - k) / (1.0 - k);
+
+    (c, m, y, k)
+}
+
+/// Convert RGB color to HSV color space.
+fn rgb_to_hsv(rgb: Rgb<u8>) -> (f64, f64, f64) {
+    let r = rgb[0] as f64 / 255.0;
+    let g = rgb[1] as f64 / 255.0;
+    let b = rgb[2] as f64 / 255.0;
+
+    let c_max = r.max(g).max(b);
+    let c_min = r.min(g).min(b);
+    let delta = c_max - c_min;
+
+    let hue = if delta == 0.0 {
+        0.0
+    } else if c_max == r {
+        60.0 * (((g - b) / delta) % 6.0)
+    } else if c_max == g {
+        60.0 * (((b - r) / delta) + 2.0)
+    } else {
+        60.0 * (((r - g) / delta) + 4.0)
+    };
+
+    let saturation = if c_max == 0.0 { 0.0 } else { delta / c_max };
+
+    let value = c_max;
+
+    (hue, saturation, value)
+}
+
+/// Colour criteria a pixel has to meet to count as part of the bobber.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Thresholds {
+    /// Pixels must have a cyan component below this value.
+    pub cyan_max: f64,
+    /// Pixels must have a saturation above this value.
+    pub saturation_min: f64,
+}
+impl Thresholds {
+    pub fn matches(&self, rgb: Rgb<u8>) -> bool {
+        let (cyan, _, _, _) = rgb_to_cmyk(rgb);
+        let (_, saturation, _) = rgb_to_hsv(rgb);
+        cyan < self.cyan_max && saturation > self.saturation_min
+    }
+}
+impl Default for Thresholds {
+    fn default() -> Self {
+        Self {
+            cyan_max: 0.1,
+            saturation_min: 0.4,
+        }
+    }
+}
+
+/// Precomputed classification of the quantized RGB cube.
+///
+/// Every channel is reduced to its top 5 bits, and each of the resulting 32768 cells is
+/// classified once at the centre of the cell, so looking up a pixel is a single index.
+///
+/// A pixel is at most 4 levels per channel away from the centre of its cell, so it can
+/// only be classified differently from [`Thresholds::matches`] in the cells the thresholds
+/// run through. With the default thresholds that's about 1% of all colours.
+#[derive(Clone)]
+pub struct ColorLut {
+    table: Vec<bool>,
+}
+impl ColorLut {
+    pub fn new(thresholds: &Thresholds) -> Self {
+        let mut table = vec![false; LEVELS * LEVELS * LEVELS];
+        for r in 0..LEVELS {
+            for g in 0..LEVELS {
+                for b in 0..LEVELS {
+                    let rgb = Rgb([centre(r), centre(g), centre(b)]);
+                    table[Self::index(rgb.0)] = thresholds.matches(rgb);
+                }
+            }
+        }
+        Self { table }
+    }
+    #[inline]
+    fn index([r, g, b]: [u8; 3]) -> usize {
+        let shift = 8 - BITS;
+        ((r as usize >> shift) << (2 * BITS))
+            | ((g as usize >> shift) << BITS)
+            | (b as usize >> shift)
+    }
+    #[inline]
+    pub fn matches(&self, rgb: [u8; 3]) -> bool {
+        self.table[Self::index(rgb)]
+    }
+}
+impl Default for ColorLut {
+    fn default() -> Self {
+        Self::new(&Thresholds::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn agrees_with_the_thresholds_at_cell_centres() {
+        let thresholds = Thresholds::default();
+        let lut = ColorLut::new(&thresholds);
+        let mut matched = 0;
+        for r in 0..LEVELS {
+            for g in 0..LEVELS {
+                for b in 0..LEVELS {
+                    let rgb = [centre(r), centre(g), centre(b)];
+                    assert_eq!(lut.matches(rgb), thresholds.matches(Rgb(rgb)), "{rgb:?}");
+                    matched += lut.matches(rgb) as usize;
+                }
+            }
+        }
+        // Neither everything nor nothing, or the comparison says little
+        assert!(matched > 0 && matched < LEVELS.pow(3));
+    }
+
+    #[test]
+    fn disagrees_with_the_thresholds_on_few_colours() {
+        let thresholds = Thresholds::default();
+        let lut = ColorLut::new(&thresholds);
+        let (mut total, mut different) = (0, 0);
+        for r in (0..=255).step_by(3) {
+            for g in (0..=255).step_by(3) {
+                for b in (0..=255).step_by(3) {
+                    total += 1;
+                    different +=
+                        (lut.matches([r, g, b]) != thresholds.matches(Rgb([r, g, b]))) as usize;
+                }
+            }
+        }
+        // Only colours near the thresholds can land in a cell classified the other way
+        let share = different as f64 / total as f64;
+        assert!(share < 0.02, "{share}");
+    }
+}
@@ -0,0 +1,611 @@
+use std::{
+    sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender, SyncSender},
+    time::{Duration, Instant},
+};
+
+use eyre::Context;
+use image::RgbImage;
+
+use crate::{
+    control::{AudioBuffer, ToBrain, ToController, WindowEvent},
+    profile::Profile,
+};
+
+mod audio;
+mod dataset;
+mod debug;
+mod detect;
+mod eval;
+mod fusion;
+mod health;
+mod light;
+mod lut;
+#[cfg(feature = "onnx")]
+mod onnx;
+mod scale;
+mod screen;
+mod state;
+mod synth;
+mod template;
+mod tracker;
+mod verify;
+pub use audio::{SplashDetector, SplashParams};
+pub use dataset::{Dataset, FrameLabel, Labels};
+pub use debug::{annotate, DebugMode, DebugOutput, DebugSink};
+pub use detect::{ColorDetector, ConfidencePolicy, Detection, Detector, Rect};
+pub use eval::{evaluate, BiteOutcome, Report};
+pub use fusion::{BiteFusion, Cue, Decision, FrameDifference, FusionParams};
+pub use health::{FrameHealth, HealthChange, HealthParams};
+pub use light::{Illumination, LightingParams};
+pub use lut::{ColorLut, Thresholds};
+#[cfg(feature = "onnx")]
+pub use onnx::OnnxDetector;
+pub use scale::{FrameScale, RelativeRect, ScaleParams};
+pub use screen::{ScreenAction, ScreenChange, ScreenClassifier, ScreenParams};
+pub use state::{FishingEvent, FishingMachine, FishingState, StateTimings, Transition};
+pub use synth::{SceneGenerator, SceneParams};
+pub use template::{Measure, Template};
+pub use tracker::{TrackUpdate, Tracker, TrackerParams};
+pub use verify::{CatchCheck, CatchStats, LootDetector, LootParams};
+
+/// Watches a settled bobber for the jump caused by a bite.
+pub struct HookCast {
+    bobber_pos: Option<[i32; 2]>,
+    bobber_size: Option<f64>,
+    area: Option<Rect>,
+}
+impl HookCast {
+    pub fn new() -> Self {
+        Self {
+            bobber_pos: None,
+            bobber_size: None,
+            area: None,
+        }
+    }
+    /// Returns how far `detection` is from the settled bobber, in px.
+    ///
+    /// The first detection is taken as the settled position, and `None` returned.
+    pub fn displacement(&mut self, detection: &Detection) -> Option<f64> {
+        let [nx, ny] = detection.pixel_pos();
+        if let Some([bx, by]) = self.bobber_pos {
+            Some(((bx - nx) as f64).hypot((by - ny) as f64))
+        } else {
+            self.bobber_pos = Some([nx, ny]);
+            let bbox = detection.bbox;
+            let size = bbox.width.max(bbox.height);
+            self.bobber_size = Some(size as f64);
+            self.area = Some(bbox.grow(size / 2));
+            None
+        }
+    }
+    /// The settled position bites are measured against, once it's known.
+    pub fn anchor(&self) -> Option<[i32; 2]> {
+        self.bobber_pos
+    }
+    /// How big the settled bobber is, in px.
+    pub fn bobber_size(&self) -> Option<f64> {
+        self.bobber_size
+    }
+    /// The surroundings of the settled bobber, where a bite makes the water change.
+    pub fn area(&self) -> Option<Rect> {
+        self.area
+    }
+}
+impl Default for HookCast {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// How often the state machine gets to expire states while no frames are arriving.
+const TICK: Duration = Duration::from_millis(50);
+/// How often the warning about why fishing is paused is repeated.
+const WARN_INTERVAL: Duration = Duration::from_secs(10);
+
+pub struct Brain {
+    machine: FishingMachine,
+    ongoing: Option<HookCast>,
+    detector: Box<dyn Detector>,
+    policy: ConfidencePolicy,
+    tracker: Tracker,
+    illumination: Illumination,
+    scale_params: ScaleParams,
+    /// The scale of the last frame seen.
+    scale: FrameScale,
+    splash: SplashDetector,
+    fusion: BiteFusion,
+    difference: FrameDifference,
+    /// The last accepted bobber position, where the click goes on a bite.
+    target: Option<[i32; 2]>,
+    /// Confidence of the detection `target` comes from.
+    confidence: f32,
+    /// Whether the bobber was found in the last frame.
+    bobber_visible: bool,
+    loot: LootDetector,
+    /// Checks how the last click worked out, until looting is over.
+    catch: Option<CatchCheck>,
+    stats: CatchStats,
+    health: FrameHealth,
+    screens: ScreenClassifier,
+    /// Whether the game window is hidden or closed, which of the two it was.
+    window_gone: Option<WindowEvent>,
+    /// When the last warning about pausing was printed.
+    warned: Option<Instant>,
+    observer: Option<Sender<Transition>>,
+    debug: Option<DebugSink>,
+}
+impl Brain {
+    /// A brain looking for the bobber with `detector`, tuned by `profile`.
+    pub fn new(profile: &Profile, detector: Box<dyn Detector>) -> eyre::Result<Self> {
+        Ok(Self {
+            machine: FishingMachine::new(profile.timings, Instant::now()),
+            ongoing: None,
+            detector,
+            policy: profile.confidence,
+            tracker: Tracker::new(profile.tracker),
+            illumination: Illumination::new(profile.lighting.clone(), &profile.thresholds),
+            scale_params: profile.scale,
+            scale: FrameScale::new(1, None, &profile.scale),
+            splash: SplashDetector::new(profile.splash),
+            fusion: BiteFusion::new(profile.fusion),
+            difference: FrameDifference::default(),
+            target: None,
+            confidence: 0.0,
+            bobber_visible: false,
+            loot: LootDetector::new(profile.loot.clone())?,
+            catch: None,
+            stats: CatchStats::default(),
+            health: FrameHealth::new(profile.health),
+            screens: ScreenClassifier::new(&profile.screens)?,
+            window_gone: None,
+            warned: None,
+            observer: None,
+            debug: None,
+        })
+    }
+    /// Writes annotated copies of the frames the brain sees to `sink`.
+    pub fn with_debug(mut self, sink: DebugSink) -> Self {
+        self.debug = Some(sink);
+        self
+    }
+    /// Returns a channel on which every state transition of the brain is reported.
+    pub fn observe(&mut self) -> Receiver<Transition> {
+        let (send, recv) = channel();
+        self.observer = Some(send);
+        recv
+    }
+    pub fn state(&self) -> FishingState {
+        self.machine.state()
+    }
+    /// The detector looking for the bobber, to measure it on its own.
+    pub fn detector_mut(&mut self) -> &mut dyn Detector {
+        &mut *self.detector
+    }
+    /// Why fishing can't go on right now, if anything stops it.
+    fn pause_reason(&self) -> Option<String> {
+        match self.window_gone {
+            Some(WindowEvent::Closed) => return Some("the game window is closed".into()),
+            Some(_) => return Some("the game window is hidden".into()),
+            None => {}
+        }
+        match (self.health.problem(), self.screens.current()) {
+            (Some(problem), _) => Some(problem.to_string()),
+            (None, Some(state)) => Some(format!("the game shows {}", state.name)),
+            (None, None) => None,
+        }
+    }
+    /// Repeats why fishing is paused every once in a while, so it doesn't look stuck.
+    fn remind(&mut self, now: Instant) {
+        if self.state() != FishingState::Paused
+            || self
+                .warned
+                .is_some_and(|at| now.saturating_duration_since(at) < WARN_INTERVAL)
+        {
+            return;
+        }
+        if let Some(reason) = self.pause_reason() {
+            println!("Warning: still paused, {reason}");
+            self.warned = Some(now);
+        }
+    }
+    /// Pauses while the game window is gone. Fishing resumes with the first frame after
+    /// it's back.
+    fn window(
+        &mut self,
+        event: WindowEvent,
+        now: Instant,
+        output: &SyncSender<ToController>,
+    ) -> eyre::Result<()> {
+        match event {
+            // The eyes repeat it while they wait for the window
+            WindowEvent::Hidden | WindowEvent::Closed if self.window_gone == Some(event) => {}
+            WindowEvent::Hidden | WindowEvent::Closed => {
+                let what = match event {
+                    WindowEvent::Closed => "closed",
+                    _ => "hidden",
+                };
+                println!("Warning: the game window was {what}, pausing");
+                self.window_gone = Some(event);
+                self.warned = Some(now);
+                self.handle(FishingEvent::Pause, now, output)?;
+            }
+            WindowEvent::Shown | WindowEvent::Found => {
+                if self.window_gone.take().is_some() {
+                    println!("The game window is back");
+                }
+            }
+            WindowEvent::Resized([w, h]) => println!("The game window is now {w}x{h}"),
+        }
+        Ok(())
+    }
+    /// Feeds `event` to the state machine and performs the actions of the state it enters.
+    fn handle(
+        &mut self,
+        event: FishingEvent,
+        now: Instant,
+        output: &SyncSender<ToController>,
+    ) -> eyre::Result<()> {
+        let Some(transition) = self.machine.handle(event, now) else {
+            return Ok(());
+        };
+        if let Some(observer) = &self.observer {
+            // Nobody listening anymore is not a reason to stop fishing
+            let _ = observer.send(transition);
+        }
+        match transition.to {
+            FishingState::Casting => {
+                self.tracker.reset();
+                self.target = None;
+                output.send(ToController::CastHook)?;
+            }
+            FishingState::Watching => {
+                self.ongoing = Some(HookCast::new());
+                self.fusion.reset();
+                self.difference.reset();
+            }
+            FishingState::Reeling => {
+                if let Some(target) = self.target {
+                    output.send(ToController::PerformClick(target))?;
+                }
+                let gone_frames = self.loot.params().gone_frames;
+                self.catch = Some(CatchCheck::new(self.bobber_visible, gone_frames));
+            }
+            FishingState::Idle | FishingState::Paused => self.catch = None,
+            FishingState::Settling | FishingState::Looting | FishingState::Cooldown => {}
+        }
+        if transition.from == FishingState::Looting {
+            if let Some(catch) = self.catch.take() {
+                let outcome = catch.outcome();
+                self.stats.record(outcome);
+                println!("Reeled in, {outcome}: {}", self.stats);
+            }
+        }
+        if transition.from == FishingState::Watching {
+            self.ongoing = None;
+            if let Some(debug) = &mut self.debug {
+                let name = match transition.event {
+                    FishingEvent::Bite => "bite",
+                    FishingEvent::Tick => "timeout",
+                    FishingEvent::Start | FishingEvent::Stop => "stop",
+                    FishingEvent::Pause | FishingEvent::Resume => "pause",
+                };
+                debug.event(name)?;
+            }
+        }
+        Ok(())
+    }
+    fn see(
+        &mut self,
+        frame: &RgbImage,
+        now: Instant,
+        output: &SyncSender<ToController>,
+    ) -> eyre::Result<()> {
+        match self.health.update(frame) {
+            Some(HealthChange::Unhealthy(problem)) => {
+                println!("Warning: {problem}, pausing");
+                self.warned = Some(now);
+                self.handle(FishingEvent::Pause, now, output)?;
+            }
+            Some(HealthChange::Healthy) => println!("Frames look healthy again"),
+            None => {}
+        }
+        // Broken frames can't be told apart from any screen state
+        if self.health.problem().is_none() {
+            match self.screens.classify(frame, now) {
+                Some(ScreenChange::Entered(state)) => {
+                    let (event, doing) = match state.action {
+                        ScreenAction::Pause => (FishingEvent::Pause, "pausing"),
+                        ScreenAction::Stop => (FishingEvent::Stop, "stopping"),
+                    };
+                    println!("Warning: the game shows {}, {doing}", state.name);
+                    self.warned = Some(now);
+                    self.handle(event, now, output)?;
+                }
+                Some(ScreenChange::Left(state)) => {
+                    println!("The game no longer shows {}", state.name)
+                }
+                None => {}
+            }
+        }
+        if self.pause_reason().is_some() {
+            return Ok(());
+        }
+        if self.state() == FishingState::Paused {
+            println!("Resuming");
+            self.warned = None;
+            self.handle(FishingEvent::Resume, now, output)?;
+        }
+        let bobber = self.ongoing.as_ref().and_then(HookCast::bobber_size);
+        let scale = FrameScale::new(frame.height(), bobber, &self.scale_params);
+        self.scale = scale;
+        let roi = Rect::middle_third(frame.width(), frame.height());
+        let state = self.state();
+        let loot = match state {
+            FishingState::Watching => {
+                self.loot.remember(frame);
+                false
+            }
+            FishingState::Reeling | FishingState::Looting => self.loot.appeared(frame),
+            _ => false,
+        };
+        let (lighting, regime) = self.illumination.update(frame, roi);
+        if let Some(regime) = regime.cloned() {
+            let brightness = self
+                .illumination
+                .brightness()
+                .unwrap_or(lighting.brightness);
+            println!(
+                "Lighting looks like {} (brightness {brightness:.2})",
+                regime.name
+            );
+            self.detector.set_thresholds(&regime.thresholds);
+        }
+        let normalised = self.illumination.normalise(frame, roi, lighting);
+        let frame = normalised.as_ref().unwrap_or(frame);
+        let detection = match state {
+            FishingState::Settling
+            | FishingState::Watching
+            | FishingState::Reeling
+            | FishingState::Looting => self.detector.detect(frame, roi),
+            _ => None,
+        };
+        if let Some(debug) = &mut self.debug {
+            let mask = self.detector.mask(frame, roi);
+            let anchor = self.ongoing.as_ref().and_then(HookCast::anchor);
+            debug.push(annotate(
+                frame,
+                roi,
+                mask.as_ref(),
+                detection.as_ref(),
+                anchor,
+            ))?;
+        }
+        let detection = detection.filter(|d| d.confidence >= self.policy.track);
+        self.bobber_visible = detection.is_some();
+        if let Some(catch) = &mut self.catch {
+            catch.observe(self.bobber_visible, loot);
+            return Ok(());
+        }
+        let Some(detection) = detection else {
+            return Ok(());
+        };
+        let update = self
+            .tracker
+            .update(detection.pos.map(|p| scale.px_to_frames(p)), now);
+        if update != TrackUpdate::Rejected {
+            if let Some([tx, ty]) = self
+                .tracker
+                .position()
+                .map(|p| p.map(|p| scale.frames_to_px(p)))
+            {
+                self.target = Some([tx as i32, ty as i32]);
+                self.confidence = detection.confidence;
+                output.send(ToController::MoveMouse([tx as i32, ty as i32]))?;
+            }
+        }
+        // Rejected jumps still count as cues, a bite is exactly such a jump
+        let Some(cast) = &mut self.ongoing else {
+            return Ok(());
+        };
+        if let Some(displacement) = cast.displacement(&detection) {
+            let displacement = scale.px_to_bobbers(displacement);
+            self.fusion.observe(Cue::Displacement, displacement, now);
+        }
+        if let Some(area) = cast.area() {
+            if let Some(ratio) = self.difference.measure(frame, area) {
+                self.fusion.observe(Cue::FrameDifference, ratio, now);
+            }
+        }
+        if matches!(update, TrackUpdate::Accepted | TrackUpdate::Rejected) {
+            let residual = self.tracker.normalized_innovation();
+            self.fusion.observe(Cue::TrackerResidual, residual, now);
+        }
+        let decision = self.fusion.decide(now);
+        if !decision.bite {
+            self.missed(&decision);
+            return Ok(());
+        }
+        // The bobber was pulled under right here, the filtered position lags behind
+        self.target = Some(detection.pixel_pos());
+        self.confidence = detection.confidence;
+        self.reel(decision, now, output)
+    }
+    fn hear(
+        &mut self,
+        buffer: &AudioBuffer,
+        now: Instant,
+        output: &SyncSender<ToController>,
+    ) -> eyre::Result<()> {
+        let Some(onset) = self.splash.process(buffer) else {
+            return Ok(());
+        };
+        if self.state() != FishingState::Watching {
+            return Ok(());
+        }
+        // The buffer may have waited behind frames, the splash happened when it was recorded
+        self.fusion
+            .observe(Cue::Splash, onset.ratio, buffer.captured.min(now));
+        let decision = self.fusion.decide(now);
+        if !decision.bite {
+            self.missed(&decision);
+            return Ok(());
+        }
+        self.reel(decision, now, output)
+    }
+    /// Tells about cues that fired without adding up to a bite, while debugging.
+    fn missed(&self, decision: &Decision) {
+        if self.debug.is_some() && decision.agreeing > 0 {
+            println!("No bite ({decision})");
+        }
+    }
+    /// Reels in on the bite the cues agreed on, if the bobber is known well enough to click.
+    fn reel(
+        &mut self,
+        decision: Decision,
+        now: Instant,
+        output: &SyncSender<ToController>,
+    ) -> eyre::Result<()> {
+        if self.target.is_none() {
+            println!("Bite ({decision}) but the bobber hasn't been seen, ignoring it");
+            return Ok(());
+        }
+        if self.confidence < self.policy.click {
+            println!(
+                "Ignoring bite ({decision}) at {:.2} confidence",
+                self.confidence
+            );
+            return Ok(());
+        }
+        println!("Bite ({decision})");
+        self.handle(FishingEvent::Bite, now, output)
+    }
+    /// Starts fishing at `now`.
+    pub fn start(&mut self, now: Instant, output: &SyncSender<ToController>) -> eyre::Result<()> {
+        self.handle(FishingEvent::Start, now, output)
+    }
+    /// Advances the brain to `now`, processing `input` if any arrived in the meantime.
+    ///
+    /// The brain doesn't read the clock on its own, so recorded frames can be replayed
+    /// with the times they were captured at.
+    pub fn step(
+        &mut self,
+        input: Option<&ToBrain>,
+        now: Instant,
+        output: &SyncSender<ToController>,
+    ) -> eyre::Result<()> {
+        match input {
+            Some(ToBrain::NextFrame(frame)) => self.see(frame, now, output)?,
+            Some(ToBrain::Audio(buffer)) => self.hear(buffer, now, output)?,
+            Some(ToBrain::Window(event)) => self.window(*event, now, output)?,
+            None => {}
+        }
+        self.remind(now);
+        self.handle(FishingEvent::Tick, now, output)
+    }
+    pub fn run(
+        mut self,
+        input: Receiver<ToBrain>,
+        output: SyncSender<ToController>,
+    ) -> eyre::Result<()> {
+        self.start(Instant::now(), &output)?;
+        loop {
+            let input = match input.recv_timeout(TICK) {
+                Ok(input) => Some(input),
+                Err(RecvTimeoutError::Timeout) => None,
+                Err(e) => return Err(e).wrap_err("Failed to receive next input"),
+            };
+            self.step(input.as_ref(), Instant::now(), &output)?;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{mpsc::sync_channel, Arc, Mutex};
+
+    use image::Rgb;
+
+    use super::*;
+
+    /// Finds the bobber wherever the test put it.
+    struct Puppet(Arc<Mutex<[f64; 2]>>);
+    impl Detector for Puppet {
+        fn name(&self) -> &'static str {
+            "puppet"
+        }
+        fn detect(&self, _frame: &RgbImage, _roi: Rect) -> Option<Detection> {
+            let pos = *self.0.lock().unwrap();
+            let [x, y] = pos.map(|p| p as u32);
+            Some(Detection {
+                pos,
+                bbox: Rect::new(x - 5, y - 4, 10, 8),
+                pixels: 60,
+                confidence: 1.0,
+                detector: "puppet",
+            })
+        }
+    }
+
+    /// Rippling water that's never the same twice, so the frames look healthy.
+    fn water(index: u32) -> RgbImage {
+        RgbImage::from_fn(320, 180, |x, y| {
+            let shade = ((x * 7 + y * 13 + index * 5) % 64) as u8;
+            Rgb([20 + shade / 2, 60 + shade, 80 + shade])
+        })
+    }
+
+    #[test]
+    fn reels_in_on_a_jump_the_tracker_rejects() {
+        let bobber = Arc::new(Mutex::new([160.0, 90.0]));
+        let detector = Box::new(Puppet(bobber.clone()));
+        let mut brain = Brain::new(&Profile::default(), detector).unwrap();
+        let (output, actions) = sync_channel(1000);
+        let start = Instant::now();
+        brain.start(start, &output).unwrap();
+        assert_eq!(actions.try_recv().unwrap(), ToController::CastHook);
+
+        // Long enough for the cast to land and the bobber to settle
+        let frame_interval = Duration::from_millis(50);
+        for index in 0..100 {
+            let now = start + frame_interval * index;
+            let frame = ToBrain::NextFrame(water(index));
+            brain.step(Some(&frame), now, &output).unwrap();
+        }
+        assert_eq!(brain.state(), FishingState::Watching);
+        while actions.try_recv().is_ok() {}
+
+        // Pulled under by three bobber sizes within one frame, far outside the gate
+        *bobber.lock().unwrap() = [160.0, 120.0];
+        let now = start + frame_interval * 100;
+        let frame = ToBrain::NextFrame(water(100));
+        brain.step(Some(&frame), now, &output).unwrap();
+        assert_eq!(brain.state(), FishingState::Reeling);
+        let clicks: Vec<_> = actions
+            .try_iter()
+            .filter(|action| matches!(action, ToController::PerformClick(_)))
+            .collect();
+        assert_eq!(clicks, [ToController::PerformClick([160, 120])]);
+    }
+
+    #[test]
+    fn repeated_window_events_keep_the_pause_reminder() {
+        let detector = Box::new(Puppet(Arc::new(Mutex::new([160.0, 90.0]))));
+        let mut brain = Brain::new(&Profile::default(), detector).unwrap();
+        let (output, _actions) = sync_channel(1000);
+        let start = Instant::now();
+        brain.start(start, &output).unwrap();
+
+        let hidden = ToBrain::Window(WindowEvent::Hidden);
+        brain.step(Some(&hidden), start, &output).unwrap();
+        assert_eq!(brain.state(), FishingState::Paused);
+        // Repeated every second while the window stays hidden
+        let later = start + Duration::from_secs(1);
+        brain.step(Some(&hidden), later, &output).unwrap();
+        assert_eq!(brain.warned, Some(start));
+
+        let closed = ToBrain::Window(WindowEvent::Closed);
+        brain.step(Some(&closed), later, &output).unwrap();
+        assert_eq!(brain.window_gone, Some(WindowEvent::Closed));
+        assert_eq!(brain.warned, Some(later));
+    }
+}
@@ -0,0 +1,139 @@
+use std::{
+    path::Path,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use eyre::{ensure, eyre};
+use image::{imageops, RgbImage};
+use tract_onnx::prelude::*;
+
+use super::{Detection, Detector, Rect};
+
+/// Side of the square input of models that don't fix their own input size.
+const DEFAULT_INPUT: usize = 320;
+
+fn tract_error(e: TractError) -> eyre::Report {
+    eyre!("{e:#}")
+}
+
+/// Finds the bobber with a learned model in ONNX format, run on the CPU.
+///
+/// The model takes a `1x3xHxW` tensor of RGB values from 0 to 1, the ROI resized to fit.
+/// It outputs a `1xNxK` tensor of `N` candidate boxes with `K >= 5` values each: the
+/// corners `x0, y0, x1, y1` relative to the input size and a score from 0 to 1. Any further
+/// values are ignored. The best scoring box is the bobber.
+pub struct OnnxDetector {
+    model: Arc<TypedRunnableModel>,
+    /// Width and height of the model input, in px.
+    input: [usize; 2],
+    /// Whether the last inference failed, so a lasting failure is only reported once.
+    failing: AtomicBool,
+}
+impl OnnxDetector {
+    pub fn load(path: impl AsRef<Path>) -> eyre::Result<Self> {
+        let path = path.as_ref();
+        let model = tract_onnx::onnx()
+            .model_for_path(path)
+            .map_err(|e| eyre!("Failed to load {}: {e:#}", path.display()))?;
+        let shape = model
+            .input_fact(0)
+            .and_then(|fact| fact.shape.as_concrete_finite())
+            .map_err(tract_error)?;
+        let input = match shape.as_deref() {
+            Some(&[1, 3, h, w]) => [w, h],
+            Some(shape) => eyre::bail!("Expected a 1x3xHxW input, the model takes {shape:?}"),
+            None => [DEFAULT_INPUT; 2],
+        };
+        let model = model
+            .with_input_fact(0, f32::fact([1, 3, input[1], input[0]]).into())
+            .and_then(|model| model.into_optimized())
+            .and_then(|model| model.into_runnable())
+            .map_err(tract_error)?;
+        let detector = Self {
+            model,
+            input,
+            failing: AtomicBool::new(false),
+        };
+        // Fail on a model with the wrong outputs now rather than on every frame
+        let [w, h] = input;
+        let boxes = detector.infer(&RgbImage::new(w as u32, h as u32))?;
+        ensure!(
+            boxes.rank() == 3 && boxes.shape()[2] >= 5,
+            "Expected a 1xNx5 output, the model gives {:?}",
+            boxes.shape()
+        );
+        Ok(detector)
+    }
+    /// Runs the model on `input` and reads the boxes it found, warning when it starts to fail.
+    fn boxes(&self, input: &RgbImage) -> Option<tract_ndarray::Array2<f32>> {
+        let boxes = self.infer(input).and_then(|boxes| {
+            let view = boxes.to_plain_array_view::<f32>().map_err(tract_error)?;
+            let [_, n, k] = *view.shape() else {
+                eyre::bail!(
+                    "Expected a 1xNx5 output, the model gives {:?}",
+                    view.shape()
+                );
+            };
+            Ok(view.to_shape((n, k))?.to_owned())
+        });
+        let failed = boxes.is_err();
+        if self.failing.swap(failed, Ordering::Relaxed) != failed {
+            match &boxes {
+                Err(e) => println!("Warning: the ONNX model failed, not detecting: {e:#}"),
+                Ok(_) => println!("The ONNX model works again"),
+            }
+        }
+        boxes.ok()
+    }
+    /// Runs the model on `input`, which has to be the size of the model input.
+    fn infer(&self, input: &RgbImage) -> eyre::Result<Tensor> {
+        let [w, h] = self.input;
+        let tensor: Tensor = tract_ndarray::Array4::from_shape_fn((1, 3, h, w), |(_, c, y, x)| {
+            input.get_pixel(x as u32, y as u32)[c] as f32 / 255.0
+        })
+        .into();
+        let mut outputs = self.model.run(tvec!(tensor.into())).map_err(tract_error)?;
+        Ok(outputs.remove(0).into_tensor())
+    }
+}
+impl Detector for OnnxDetector {
+    fn name(&self) -> &'static str {
+        "onnx"
+    }
+    fn detect(&self, frame: &RgbImage, roi: Rect) -> Option<Detection> {
+        let roi = roi.clamp(frame.width(), frame.height());
+        if roi.area() == 0 {
+            return None;
+        }
+        let [w, h] = self.input;
+        let crop = imageops::crop_imm(frame, roi.x, roi.y, roi.width, roi.height).to_image();
+        let input = imageops::resize(&crop, w as u32, h as u32, imageops::FilterType::Triangle);
+        let boxes = self.boxes(&input)?;
+        let best = boxes
+            .rows()
+            .into_iter()
+            .filter(|row| row.len() >= 5)
+            .max_by(|a, b| a[4].total_cmp(&b[4]))?;
+        // Back from relative input coordinates to frame pixels
+        let x = |v: f32| roi.x as f64 + (v as f64).clamp(0.0, 1.0) * roi.width as f64;
+        let y = |v: f32| roi.y as f64 + (v as f64).clamp(0.0, 1.0) * roi.height as f64;
+        let (x0, y0, x1, y1) = (x(best[0]), y(best[1]), x(best[2]), y(best[3]));
+        if x1 <= x0 || y1 <= y0 {
+            return None;
+        }
+        let bbox = Rect::from_corners(
+            [x0 as u32, y0 as u32],
+            [
+                (x1.ceil() as u32 - 1).max(x0 as u32),
+                (y1.ceil() as u32 - 1).max(y0 as u32),
+            ],
+        );
+        Some(Detection {
+            pos: [(x0 + x1) / 2.0, (y0 + y1) / 2.0],
+            bbox,
+            pixels: bbox.area(),
+            confidence: best[4].clamp(0.0, 1.0),
+            detector: self.name(),
+        })
+    }
+}
@@ -0,0 +1,188 @@
+use serde::{Deserialize, Serialize};
+
+use super::Rect;
+
+/// Tuning of the [`FrameScale`].
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScaleParams {
+    /// Size of a clearly visible bobber relative to the frame height, assumed until the
+    /// bobber of the current cast has been measured.
+    pub bobber: f64,
+}
+impl Default for ScaleParams {
+    fn default() -> Self {
+        Self { bobber: 0.02 }
+    }
+}
+
+/// Converts between pixels and the resolution independent units parameters are given in.
+///
+/// Lengths are either relative to the frame height, so they mean the same on every monitor,
+/// or to the size of the bobber, which also follows the render scale and camera distance.
+/// A new scale is taken for every frame.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FrameScale {
+    /// Height of the frame, in px.
+    pub frame: f64,
+    /// Size of the bobber, in px.
+    pub bobber: f64,
+}
+impl FrameScale {
+    /// The scale of a frame `frame_height` px high, with the bobber `bobber` px in size if it
+    /// has been measured.
+    pub fn new(frame_height: u32, bobber: Option<f64>, params: &ScaleParams) -> Self {
+        let frame = frame_height as f64;
+        Self {
+            frame,
+            bobber: bobber.unwrap_or(params.bobber * frame).max(1.0),
+        }
+    }
+    /// `px` in frame heights.
+    pub fn px_to_frames(&self, px: f64) -> f64 {
+        px / self.frame
+    }
+    /// `frames` frame heights in px.
+    pub fn frames_to_px(&self, frames: f64) -> f64 {
+        frames * self.frame
+    }
+    /// `px` in bobber sizes.
+    pub fn px_to_bobbers(&self, px: f64) -> f64 {
+        px / self.bobber
+    }
+}
+
+/// A rectangle given in fractions of the frame's width and height.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct RelativeRect {
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+}
+impl RelativeRect {
+    /// The rectangle in pixels of a `width`x`height` frame.
+    pub fn to_px(self, width: u32, height: u32) -> Rect {
+        let (w, h) = (width as f64, height as f64);
+        Rect::new(
+            (self.x * w) as u32,
+            (self.y * h) as u32,
+            (self.width * w).round() as u32,
+            (self.height * h).round() as u32,
+        )
+        .clamp(width, height)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{mpsc::sync_channel, Arc, Mutex},
+        time::{Duration, Instant},
+    };
+
+    use image::{Rgb, RgbImage};
+
+    use super::*;
+    use crate::{
+        control::{ToBrain, ToController},
+        profile::Profile,
+        recog::{Brain, Detection, Detector},
+    };
+
+    #[test]
+    fn frame_heights_and_px_round_trip() {
+        for height in [720, 1080, 2160] {
+            let scale = FrameScale::new(height, None, &ScaleParams::default());
+            for frames in [0.0, 0.02, 0.5, 1.0] {
+                let px = scale.frames_to_px(frames);
+                assert!((scale.px_to_frames(px) - frames).abs() < 1e-12);
+            }
+            assert_eq!(scale.frames_to_px(1.0), height as f64);
+            // The assumed bobber is the same share of every frame
+            assert!((scale.px_to_frames(scale.bobber) - 0.02).abs() < 1e-12);
+        }
+    }
+
+    /// Finds the bobber at a fixed share of the frame height, sized like the assumed one.
+    struct Scripted {
+        height: f64,
+        pos: Arc<Mutex<[f64; 2]>>,
+    }
+    impl Detector for Scripted {
+        fn name(&self) -> &'static str {
+            "scripted"
+        }
+        fn detect(&self, _frame: &RgbImage, _roi: Rect) -> Option<Detection> {
+            let pos = self.pos.lock().unwrap().map(|p| p * self.height);
+            let size = 0.02 * self.height;
+            let [x, y] = pos.map(|p| (p - size / 2.0) as u32);
+            Some(Detection {
+                pos,
+                bbox: Rect::new(x, y, size as u32, size as u32),
+                pixels: (size * size * 0.75) as u32,
+                confidence: 1.0,
+                detector: "scripted",
+            })
+        }
+    }
+
+    /// Runs one cast at `width`x`height`: the bobber settles, wobbles by a fifth of its
+    /// size and then plunges. Returns the state after every frame and the clicks, in frame
+    /// heights.
+    fn cast(width: u32, height: u32) -> (Vec<String>, Vec<[f64; 2]>) {
+        let pos = Arc::new(Mutex::new([0.9, 0.5]));
+        let detector = Scripted {
+            height: height as f64,
+            pos: pos.clone(),
+        };
+        let profile = Profile::default();
+        let mut brain = Brain::new(&profile, Box::new(detector)).unwrap();
+        // Two frames of water taking turns, so they are never frozen
+        let frames: Vec<_> = (0..2)
+            .map(|i| {
+                ToBrain::NextFrame(RgbImage::from_fn(width, height, |x, y| {
+                    let shade = ((x * 16 / width + y * 9 / height + i) % 8 * 8) as u8;
+                    Rgb([20 + shade / 2, 60 + shade, 80 + shade])
+                }))
+            })
+            .collect();
+        let (output, actions) = sync_channel(1000);
+        let start = Instant::now();
+        brain.start(start, &output).unwrap();
+        let mut states = Vec::new();
+        let mut clicks = Vec::new();
+        // Ten frames a second keep the big frames quick, watching starts after three seconds
+        for index in 0..48u32 {
+            match index {
+                36 => pos.lock().unwrap()[1] += 0.004,
+                37 => pos.lock().unwrap()[1] -= 0.004,
+                45 => pos.lock().unwrap()[1] += 0.03,
+                _ => {}
+            }
+            let now = start + Duration::from_millis(100) * index;
+            let frame = &frames[index as usize % 2];
+            brain.step(Some(frame), now, &output).unwrap();
+            states.push(format!("{:?}", brain.state()));
+            for action in actions.try_iter() {
+                if let ToController::PerformClick(at) = action {
+                    clicks.push(at.map(|p| p as f64 / height as f64));
+                }
+            }
+        }
+        (states, clicks)
+    }
+
+    #[test]
+    fn a_profile_decides_the_same_at_every_resolution() {
+        let (states_720, clicks_720) = cast(1280, 720);
+        let (states_4k, clicks_4k) = cast(3840, 2160);
+        assert_eq!(states_720, states_4k);
+        assert_eq!(clicks_720.len(), 1, "{states_720:?}");
+        assert_eq!(clicks_720.len(), clicks_4k.len());
+        for (a, b) in clicks_720.iter().zip(&clicks_4k) {
+            // Clicks land on whole pixels
+            assert!((a[0] - b[0]).abs() < 2.0 / 720.0 && (a[1] - b[1]).abs() < 2.0 / 720.0);
+        }
+    }
+}
@@ -0,0 +1,173 @@
+use std::{
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+use eyre::{ensure, Context};
+use image::RgbImage;
+use serde::{Deserialize, Serialize};
+
+use super::{Measure, RelativeRect, Template};
+
+/// What the brain does while the game shows a [`ScreenState`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ScreenAction {
+    /// Stop casting until the screen state is gone.
+    Pause,
+    /// Stop fishing for good.
+    Stop,
+}
+
+/// A state of the game that fishing can't go on in, recognised by a picture of it.
+///
+/// Profiles have to give the name, template and region, the rest is optional.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ScreenState {
+    /// Name for the logs, like "dead" or "bags full".
+    pub name: String,
+    /// A piece of UI cut out of a reference screenshot.
+    pub template: PathBuf,
+    /// Where to look for the template, not much bigger than it so the search stays cheap.
+    pub region: RelativeRect,
+    /// Height of the frame the template was cut from, in px.
+    #[serde(default = "ScreenState::default_frame_height")]
+    pub template_frame_height: u32,
+    /// Similarity from 0 to 1 the template has to reach.
+    #[serde(default = "ScreenState::default_threshold")]
+    pub threshold: f64,
+    #[serde(default = "ScreenState::default_action")]
+    pub action: ScreenAction,
+}
+impl ScreenState {
+    fn default_frame_height() -> u32 {
+        1080
+    }
+    fn default_threshold() -> f64 {
+        0.9
+    }
+    fn default_action() -> ScreenAction {
+        ScreenAction::Pause
+    }
+}
+
+/// Tuning of the [`ScreenClassifier`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScreenParams {
+    /// How often frames are matched against the screen states.
+    #[serde(with = "crate::profile::millis")]
+    pub interval: Duration,
+    pub states: Vec<ScreenState>,
+}
+impl Default for ScreenParams {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(1),
+            states: Vec::new(),
+        }
+    }
+}
+
+/// The game entered or left a screen state.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScreenChange {
+    Entered(ScreenState),
+    Left(ScreenState),
+}
+
+/// Recognises the screen states of the profile in the frames.
+#[derive(Debug, Clone)]
+pub struct ScreenClassifier {
+    interval: Duration,
+    states: Vec<(ScreenState, Template)>,
+    last_check: Option<Instant>,
+    current: Option<usize>,
+}
+impl ScreenClassifier {
+    pub fn new(params: &ScreenParams) -> eyre::Result<Self> {
+        let states = params
+            .states
+            .iter()
+            .map(|state| {
+                ensure!(
+                    !state.template.as_os_str().is_empty(),
+                    "Screen state {:?} has no template, give the path of a picture of it",
+                    state.name
+                );
+                let template = Template::load(&state.template, state.template_frame_height)
+                    .wrap_err_with(|| format!("Screen state {:?}", state.name))?;
+                Ok((state.clone(), template))
+            })
+            .collect::<eyre::Result<_>>()?;
+        Ok(Self {
+            interval: params.interval,
+            states,
+            last_check: None,
+            current: None,
+        })
+    }
+    /// The screen state the game was last seen in, if any.
+    pub fn current(&self) -> Option<&ScreenState> {
+        self.current.map(|i| &self.states[i].0)
+    }
+    /// Matches `frame` against the screen states, if it's time to, and returns whether the
+    /// game entered or left one.
+    ///
+    /// The first state that matches wins, so more specific states should come first.
+    pub fn classify(&mut self, frame: &RgbImage, now: Instant) -> Option<ScreenChange> {
+        if self.states.is_empty()
+            || self
+                .last_check
+                .is_some_and(|at| now.saturating_duration_since(at) < self.interval)
+        {
+            return None;
+        }
+        self.last_check = Some(now);
+        let matched = self.states.iter().position(|(state, template)| {
+            let area = state.region.to_px(frame.width(), frame.height());
+            template.similarity(frame, area, Measure::Correlation) >= state.threshold
+        });
+        if matched == self.current {
+            return None;
+        }
+        let previous = std::mem::replace(&mut self.current, matched);
+        match (matched, previous) {
+            (Some(i), _) => Some(ScreenChange::Entered(self.states[i].0.clone())),
+            (None, Some(i)) => Some(ScreenChange::Left(self.states[i].0.clone())),
+            (None, None) => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn states_need_a_region() {
+        let json = r#"{"name": "dead", "template": "dead.png"}"#;
+        let error = serde_json::from_str::<ScreenState>(json).unwrap_err();
+        assert!(error.to_string().contains("region"), "{error}");
+        let json = r#"{"name": "dead", "template": "dead.png",
+            "region": {"x": 0.4, "y": 0.1, "width": 0.2, "height": 0.1}}"#;
+        let state: ScreenState = serde_json::from_str(json).unwrap();
+        assert_eq!(state.threshold, 0.9);
+        assert_eq!(state.action, ScreenAction::Pause);
+    }
+
+    #[test]
+    fn states_without_a_template_are_refused() {
+        let json = r#"{"name": "dead", "template": "",
+            "region": {"x": 0.4, "y": 0.1, "width": 0.2, "height": 0.1}}"#;
+        let params = ScreenParams {
+            states: vec![serde_json::from_str(json).unwrap()],
+            ..ScreenParams::default()
+        };
+        let error = ScreenClassifier::new(&params).unwrap_err();
+        assert!(
+            error.to_string().contains("\"dead\" has no template"),
+            "{error}"
+        );
+    }
+}
@@ -0,0 +1,310 @@
+use std::{
+    fmt,
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+
+/// The phases of a single fishing attempt.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FishingState {
+    /// Not fishing, waiting for [`FishingEvent::Start`].
+    Idle,
+    /// The cast key was pressed, the bobber is flying.
+    Casting,
+    /// The bobber landed and is still bouncing on the waves.
+    Settling,
+    /// The bobber is still, watching it for a bite.
+    Watching,
+    /// A bite was detected and the bobber is being clicked.
+    Reeling,
+    /// Waiting for the loot to be picked up.
+    Looting,
+    /// Short pause before the next cast.
+    Cooldown,
+    /// The captured frames look broken, waiting for them to recover before casting again.
+    Paused,
+}
+impl fmt::Display for FishingState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
+
+/// Everything that can move the [`FishingMachine`] to another state.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FishingEvent {
+    /// Time has passed, states with a duration may expire.
+    Tick,
+    /// Start fishing.
+    Start,
+    /// Stop fishing, whatever is going on.
+    Stop,
+    /// The bite detector fired.
+    Bite,
+    /// The frames stopped looking like the game.
+    Pause,
+    /// The frames look like the game again.
+    Resume,
+}
+
+/// How long each state lasts before it expires on a [`FishingEvent::Tick`].
+///
+/// Profiles give the durations in milliseconds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StateTimings {
+    #[serde(with = "crate::profile::millis")]
+    pub casting: Duration,
+    #[serde(with = "crate::profile::millis")]
+    pub settling: Duration,
+    /// Longest time to wait for a bite before giving up on the cast.
+    #[serde(with = "crate::profile::millis")]
+    pub watching: Duration,
+    #[serde(with = "crate::profile::millis")]
+    pub reeling: Duration,
+    #[serde(with = "crate::profile::millis")]
+    pub looting: Duration,
+    #[serde(with = "crate::profile::millis")]
+    pub cooldown: Duration,
+}
+impl StateTimings {
+    /// The time after which `state` expires, if it does.
+    pub fn limit(&self, state: FishingState) -> Option<Duration> {
+        match state {
+            FishingState::Idle => None,
+            FishingState::Casting => Some(self.casting),
+            FishingState::Settling => Some(self.settling),
+            FishingState::Watching => Some(self.watching),
+            FishingState::Reeling => Some(self.reeling),
+            FishingState::Looting => Some(self.looting),
+            FishingState::Cooldown => Some(self.cooldown),
+            FishingState::Paused => None,
+        }
+    }
+}
+impl Default for StateTimings {
+    fn default() -> Self {
+        Self {
+            casting: Duration::from_millis(1500),
+            settling: Duration::from_millis(1500),
+            watching: Duration::from_secs(27),
+            reeling: Duration::from_millis(200),
+            looting: Duration::from_millis(1000),
+            cooldown: Duration::from_millis(500),
+        }
+    }
+}
+
+/// A change of state, along with the event that caused it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Transition {
+    pub from: FishingState,
+    pub to: FishingState,
+    pub event: FishingEvent,
+    pub at: Instant,
+}
+impl fmt::Display for Transition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} -> {} ({:?})", self.from, self.to, self.event)
+    }
+}
+
+/// The fishing loop as an explicit state machine.
+///
+/// The machine never reads the clock itself, every event comes with the time it happened at,
+/// so it can be driven step by step.
+#[derive(Debug, Clone)]
+pub struct FishingMachine {
+    state: FishingState,
+    since: Instant,
+    timings: StateTimings,
+}
+impl FishingMachine {
+    pub fn new(timings: StateTimings, now: Instant) -> Self {
+        Self {
+            state: FishingState::Idle,
+            since: now,
+            timings,
+        }
+    }
+    pub fn state(&self) -> FishingState {
+        self.state
+    }
+    /// How long the machine has been in the current state.
+    pub fn elapsed(&self, now: Instant) -> Duration {
+        now.saturating_duration_since(self.since)
+    }
+    /// The state `event` leads to from the current one, if it changes anything.
+    fn next(&self, event: FishingEvent, now: Instant) -> Option<FishingState> {
+        use FishingEvent as E;
+        use FishingState as S;
+        match (self.state, event) {
+            (S::Idle, E::Start) => Some(S::Casting),
+            (S::Idle, _) => None,
+            (_, E::Stop) => Some(S::Idle),
+            (S::Paused, E::Resume) => Some(S::Casting),
+            (S::Paused, _) => None,
+            (_, E::Pause) => Some(S::Paused),
+            (S::Watching, E::Bite) => Some(S::Reeling),
+            (state, E::Tick) => {
+                let limit = self.timings.limit(state)?;
+                if self.elapsed(now) < limit {
+                    return None;
+                }
+                match state {
+                    S::Casting => Some(S::Settling),
+                    S::Settling => Some(S::Watching),
+                    S::Watching => Some(S::Cooldown),
+                    S::Reeling => Some(S::Looting),
+                    S::Looting => Some(S::Cooldown),
+                    S::Cooldown => Some(S::Casting),
+                    S::Idle | S::Paused => None,
+                }
+            }
+            _ => None,
+        }
+    }
+    /// Feeds `event` into the machine, returning the transition it caused.
+    pub fn handle(&mut self, event: FishingEvent, now: Instant) -> Option<Transition> {
+        let to = self.next(event, now)?;
+        let transition = Transition {
+            from: self.state,
+            to,
+            event,
+            at: now,
+        };
+        self.state = to;
+        self.since = now;
+        Some(transition)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use FishingEvent as E;
+    use FishingState as S;
+
+    /// A machine that was put into `state` at `start`.
+    fn machine_in(state: FishingState, start: Instant) -> FishingMachine {
+        FishingMachine {
+            state,
+            since: start,
+            timings: StateTimings::default(),
+        }
+    }
+
+    #[test]
+    fn events_lead_to_the_expected_states() {
+        let start = Instant::now();
+        let table = [
+            (S::Idle, E::Start, Some(S::Casting)),
+            (S::Idle, E::Stop, None),
+            (S::Idle, E::Pause, None),
+            (S::Idle, E::Bite, None),
+            (S::Casting, E::Start, None),
+            (S::Casting, E::Bite, None),
+            (S::Settling, E::Bite, None),
+            (S::Watching, E::Bite, Some(S::Reeling)),
+            (S::Reeling, E::Bite, None),
+            (S::Watching, E::Resume, None),
+            (S::Paused, E::Resume, Some(S::Casting)),
+            (S::Paused, E::Pause, None),
+            (S::Paused, E::Bite, None),
+            (S::Paused, E::Stop, Some(S::Idle)),
+        ];
+        for (from, event, to) in table {
+            let mut machine = machine_in(from, start);
+            let transition = machine.handle(event, start);
+            assert_eq!(transition.map(|t| t.to), to, "{from} on {event:?}");
+            assert_eq!(machine.state(), to.unwrap_or(from), "{from} on {event:?}");
+        }
+    }
+
+    #[test]
+    fn every_active_state_pauses_and_stops() {
+        let start = Instant::now();
+        for from in [
+            S::Casting,
+            S::Settling,
+            S::Watching,
+            S::Reeling,
+            S::Looting,
+            S::Cooldown,
+        ] {
+            let mut machine = machine_in(from, start);
+            assert_eq!(
+                machine.handle(E::Pause, start).map(|t| t.to),
+                Some(S::Paused)
+            );
+            let mut machine = machine_in(from, start);
+            assert_eq!(machine.handle(E::Stop, start).map(|t| t.to), Some(S::Idle));
+        }
+    }
+
+    #[test]
+    fn states_expire_after_their_timings() {
+        let timings = StateTimings::default();
+        let start = Instant::now();
+        let table = [
+            (S::Casting, timings.casting, S::Settling),
+            (S::Settling, timings.settling, S::Watching),
+            (S::Watching, timings.watching, S::Cooldown),
+            (S::Reeling, timings.reeling, S::Looting),
+            (S::Looting, timings.looting, S::Cooldown),
+            (S::Cooldown, timings.cooldown, S::Casting),
+        ];
+        for (from, limit, to) in table {
+            let mut machine = machine_in(from, start);
+            let early = start + limit - Duration::from_millis(1);
+            assert_eq!(machine.handle(E::Tick, early), None, "{from} expired early");
+            let transition = machine.handle(E::Tick, start + limit).unwrap();
+            assert_eq!((transition.from, transition.to), (from, to));
+            assert_eq!(transition.event, E::Tick);
+        }
+    }
+
+    #[test]
+    fn idle_and_paused_never_expire() {
+        let start = Instant::now();
+        let much_later = start + Duration::from_secs(3600);
+        for state in [S::Idle, S::Paused] {
+            let mut machine = machine_in(state, start);
+            assert_eq!(machine.handle(E::Tick, much_later), None);
+        }
+    }
+
+    #[test]
+    fn timeouts_count_from_the_last_transition() {
+        let timings = StateTimings::default();
+        let start = Instant::now();
+        let mut machine = FishingMachine::new(timings, start);
+        machine.handle(E::Start, start).unwrap();
+        let landed = start + timings.casting;
+        assert_eq!(machine.handle(E::Tick, landed).unwrap().to, S::Settling);
+        assert_eq!(machine.elapsed(landed), Duration::ZERO);
+        // The time spent casting doesn't count towards settling
+        let early = landed + timings.settling - Duration::from_millis(1);
+        assert_eq!(machine.handle(E::Tick, early), None);
+        let settled = landed + timings.settling;
+        assert_eq!(machine.handle(E::Tick, settled).unwrap().to, S::Watching);
+    }
+
+    #[test]
+    fn resuming_casts_afresh() {
+        let timings = StateTimings::default();
+        let start = Instant::now();
+        let mut machine = FishingMachine::new(timings, start);
+        machine.handle(E::Start, start).unwrap();
+        let paused = start + Duration::from_millis(100);
+        machine.handle(E::Pause, paused).unwrap();
+        let resumed = paused + Duration::from_secs(60);
+        let transition = machine.handle(E::Resume, resumed).unwrap();
+        assert_eq!((transition.from, transition.to), (S::Paused, S::Casting));
+        // The cast starts over instead of carrying the time spent paused
+        let early = resumed + timings.casting - Duration::from_millis(1);
+        assert_eq!(machine.handle(E::Tick, early), None);
+    }
+}
@@ -0,0 +1,379 @@
+use std::{f64::consts::TAU, path::PathBuf};
+
+use image::{Rgb, RgbImage};
+
+use super::{FrameLabel, Labels, Rect};
+
+/// Small deterministic generator, so a scene only depends on its seed.
+#[derive(Debug, Clone)]
+struct SplitMix64(u64);
+impl SplitMix64 {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+    /// Uniform in `[0, 1)`.
+    fn unit(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+    fn range(&mut self, (min, max): (f64, f64)) -> f64 {
+        min + (max - min) * self.unit()
+    }
+}
+
+/// Stateless per-pixel noise in `[0, 1)`.
+fn hash_noise(seed: u64, x: u32, y: u32) -> f64 {
+    SplitMix64(seed ^ ((x as u64) << 32 | y as u64)).unit()
+}
+
+fn hsv_to_rgb(hue: f64, saturation: f64, value: f64) -> Rgb<u8> {
+    let c = value * saturation;
+    let h = hue.rem_euclid(360.0) / 60.0;
+    let x = c * (1.0 - (h % 2.0 - 1.0).abs());
+    let (r, g, b) = match h as u32 {
+        0 => (c, x, 0.0),
+        1 => (x, c, 0.0),
+        2 => (0.0, c, x),
+        3 => (0.0, x, c),
+        4 => (x, 0.0, c),
+        _ => (c, 0.0, x),
+    };
+    let m = value - c;
+    Rgb([r, g, b].map(|v| ((v + m) * 255.0).round().clamp(0.0, 255.0) as u8))
+}
+
+fn scale(color: Rgb<u8>, factor: f64) -> Rgb<u8> {
+    Rgb(color
+        .0
+        .map(|c| (c as f64 * factor).round().clamp(0.0, 255.0) as u8))
+}
+
+/// What kind of scenes the [`SceneGenerator`] renders. Ranges are sampled once per scene.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SceneParams {
+    pub width: u32,
+    pub height: u32,
+    pub fps: f64,
+    /// Length of the recording, in seconds from the cast.
+    pub duration: f64,
+    /// When the bobber lands on the water, in seconds from the cast.
+    pub landing: f64,
+    /// Range of times the fish bites at, in seconds from the cast.
+    pub bite: (f64, f64),
+    /// Chance that a fish bites at all.
+    pub bite_chance: f64,
+    /// Radius of the bobber, relative to the frame height.
+    pub radius: (f64, f64),
+    /// Shift of the bobber's hue away from pure red, in degrees.
+    pub hue: (f64, f64),
+    /// Factor the bobber's colours are multiplied with.
+    pub brightness: (f64, f64),
+    /// Number of red interface elements around the edges of the frame.
+    pub ui_elements: usize,
+    /// Number of reddish sparks drifting over the scene.
+    pub particles: usize,
+}
+impl Default for SceneParams {
+    fn default() -> Self {
+        Self {
+            width: 640,
+            height: 360,
+            fps: 20.0,
+            duration: 8.0,
+            landing: 1.5,
+            bite: (4.0, 7.0),
+            bite_chance: 0.8,
+            radius: (0.022, 0.044),
+            hue: (-12.0, 12.0),
+            brightness: (0.7, 1.1),
+            ui_elements: 4,
+            particles: 12,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Particle {
+    start: [f64; 2],
+    velocity: [f64; 2],
+    /// Seconds from the cast the particle is visible in.
+    life: (f64, f64),
+    color: Rgb<u8>,
+}
+
+/// Renders fishing scenes procedurally along with their ground truth labels.
+///
+/// Everything is derived from the seed, so the same seed always gives the same frames.
+#[derive(Debug, Clone)]
+pub struct SceneGenerator {
+    params: SceneParams,
+    seed: u64,
+    center: [f64; 2],
+    radius: f64,
+    red: Rgb<u8>,
+    white: Rgb<u8>,
+    bite: Option<f64>,
+    ui: Vec<(Rect, Rgb<u8>)>,
+    particles: Vec<Particle>,
+}
+impl SceneGenerator {
+    pub fn new(params: SceneParams, seed: u64) -> Self {
+        let mut rng = SplitMix64(seed);
+        let (w, h) = (params.width as f64, params.height as f64);
+        // Somewhere in the middle of the screen, where the cast usually lands
+        let center = [rng.range((w * 0.4, w * 0.6)), rng.range((h * 0.4, h * 0.6))];
+        let radius = rng.range(params.radius) * h;
+        let brightness = rng.range(params.brightness);
+        let red = scale(hsv_to_rgb(rng.range(params.hue), 0.88, 0.8), brightness);
+        let white = scale(Rgb([235, 230, 215]), brightness);
+        let bite = (rng.unit() < params.bite_chance).then(|| rng.range(params.bite));
+        let ui = (0..params.ui_elements)
+            .map(|i| {
+                let width = rng.range((w * 0.05, w * 0.2)) as u32;
+                let height = rng.range((h * 0.02, h * 0.05)).max(2.0) as u32;
+                // Alternate between the top and the bottom strip of the screen
+                let y = if i % 2 == 0 {
+                    rng.range((0.0, h * 0.25 - height as f64))
+                } else {
+                    rng.range((h * 0.75, h - height as f64))
+                };
+                let x = rng.range((0.0, w - width as f64));
+                let color = hsv_to_rgb(rng.range((-10.0, 10.0)), 0.9, rng.range((0.6, 0.9)));
+                (Rect::new(x as u32, y as u32, width, height), color)
+            })
+            .collect();
+        let particles = (0..params.particles)
+            .map(|_| {
+                let born = rng.range((0.0, params.duration));
+                Particle {
+                    start: [rng.range((0.0, w)), rng.range((0.0, h))],
+                    velocity: [
+                        rng.range((-0.055, 0.055)) * h,
+                        rng.range((-0.083, -0.014)) * h,
+                    ],
+                    life: (born, born + rng.range((0.3, 1.5))),
+                    color: hsv_to_rgb(rng.range((0.0, 30.0)), 0.8, 0.95),
+                }
+            })
+            .collect();
+        Self {
+            params,
+            seed,
+            center,
+            radius,
+            red,
+            white,
+            bite,
+            ui,
+            particles,
+        }
+    }
+    pub fn frame_count(&self) -> usize {
+        (self.params.duration * self.params.fps).round() as usize
+    }
+    fn time(&self, index: usize) -> f64 {
+        index as f64 / self.params.fps
+    }
+    /// Index of the first frame showing the bite.
+    pub fn bite_frame(&self) -> Option<usize> {
+        self.bite.map(|t| (t * self.params.fps).ceil() as usize)
+    }
+    /// Where the bobber is at `t` seconds from the cast, if it's on the water yet.
+    fn bobber(&self, t: f64) -> Option<[f64; 2]> {
+        let since_landing = t - self.params.landing;
+        if since_landing < 0.0 {
+            return None;
+        }
+        let r = self.radius;
+        // The bobber bounces after landing and then just rides the waves
+        let bounce = 0.4 * r * (-since_landing * 3.0).exp() * (since_landing * 12.0).sin();
+        let mut dx = 0.04 * r * (TAU * t / 2.3).sin();
+        let mut dy = 0.08 * r * (TAU * t / 1.7).sin() + bounce;
+        if let Some(bite) = self.bite.filter(|&bite| t >= bite) {
+            // Pulled under sharply, then slowly floating back up
+            let since = t - bite;
+            let dip = if since < 0.1 {
+                since / 0.1
+            } else {
+                (-(since - 0.1) * 2.0).exp()
+            };
+            dy += 0.9 * r * dip;
+            dx += 0.2 * r * dip * (since * 30.0).sin();
+        }
+        Some([self.center[0] + dx, self.center[1] + dy])
+    }
+    fn bobber_rect(&self, [cx, cy]: [f64; 2]) -> Rect {
+        let (rx, ry) = (self.radius, self.radius * 0.8);
+        let x0 = (cx - rx).floor().max(0.0) as u32;
+        let y0 = (cy - ry).floor().max(0.0) as u32;
+        let x1 = (cx + rx).ceil().max(0.0) as u32;
+        let y1 = (cy + ry).ceil().max(0.0) as u32;
+        Rect::from_corners([x0, y0], [x1, y1]).clamp(self.params.width, self.params.height)
+    }
+    fn draw_water(&self, img: &mut RgbImage, t: f64) {
+        let seed = self.seed;
+        for (x, y, pixel) in img.enumerate_pixels_mut() {
+            let (fx, fy) = (x as f64, y as f64);
+            let waves = (fx * 0.05 + fy * 0.02 + t * 1.3).sin()
+                + 0.6 * ((fx - fy) * 0.031 - t * 0.9).sin()
+                + 0.3 * (fy * 0.11 + t * 2.1).sin();
+            let shade = 0.5 + 0.12 * waves + 0.1 * (hash_noise(seed, x, y) - 0.5);
+            let shade = shade.clamp(0.0, 1.0);
+            *pixel = Rgb([
+                (20.0 + 40.0 * shade) as u8,
+                (60.0 + 90.0 * shade) as u8,
+                (80.0 + 100.0 * shade) as u8,
+            ]);
+        }
+    }
+    fn draw_bobber(&self, img: &mut RgbImage, center @ [cx, cy]: [f64; 2]) {
+        let rect = self.bobber_rect(center);
+        let (rx, ry) = (self.radius, self.radius * 0.8);
+        for y in rect.y..rect.bottom() {
+            for x in rect.x..rect.right() {
+                let dx = (x as f64 + 0.5 - cx) / rx;
+                let dy = (y as f64 + 0.5 - cy) / ry;
+                let d = dx * dx + dy * dy;
+                if d > 1.0 {
+                    continue;
+                }
+                let color = if dy < 0.25 { self.red } else { self.white };
+                // Darker towards the rim, so it reads as round
+                img.put_pixel(x, y, scale(color, 1.0 - 0.3 * d));
+            }
+        }
+    }
+    fn draw_splash(&self, img: &mut RgbImage, [cx, cy]: [f64; 2], since_bite: f64) {
+        if !(0.0..0.6).contains(&since_bite) {
+            return;
+        }
+        let radius = self.radius * (1.2 + since_bite * 4.0);
+        let steps = (radius * TAU) as usize;
+        for i in 0..steps {
+            let a = i as f64 / steps as f64 * TAU;
+            let x = cx + radius * a.cos();
+            let y = cy + radius * 0.5 * a.sin();
+            if x >= 0.0 && y >= 0.0 && (x as u32) < img.width() && (y as u32) < img.height() {
+                img.put_pixel(x as u32, y as u32, Rgb([220, 235, 240]));
+            }
+        }
+    }
+    /// Renders the `index`th frame along with its label.
+    pub fn render(&self, index: usize) -> (RgbImage, Option<Rect>) {
+        let t = self.time(index);
+        let mut img = RgbImage::new(self.params.width, self.params.height);
+        self.draw_water(&mut img, t);
+        let bobber = self.bobber(t);
+        if let Some(center) = bobber {
+            self.draw_bobber(&mut img, center);
+            if let Some(bite) = self.bite {
+                self.draw_splash(&mut img, center, t - bite);
+            }
+        }
+        for particle in &self.particles {
+            if !(particle.life.0..particle.life.1).contains(&t) {
+                continue;
+            }
+            let age = t - particle.life.0;
+            let x = particle.start[0] + particle.velocity[0] * age;
+            let y = particle.start[1] + particle.velocity[1] * age;
+            for [px, py] in [[x, y], [x + 1.0, y], [x, y + 1.0], [x + 1.0, y + 1.0]] {
+                if px >= 0.0 && py >= 0.0 && (px as u32) < img.width() && (py as u32) < img.height()
+                {
+                    img.put_pixel(px as u32, py as u32, particle.color);
+                }
+            }
+        }
+        for (rect, color) in &self.ui {
+            for y in rect.y..rect.bottom() {
+                for x in rect.x..rect.right() {
+                    img.put_pixel(x, y, *color);
+                }
+            }
+        }
+        (img, bobber.map(|center| self.bobber_rect(center)))
+    }
+    /// Renders the whole recording, frames being named like `000042.png`.
+    pub fn generate(&self) -> (Labels, Vec<RgbImage>) {
+        let (frames, labels) = (0..self.frame_count())
+            .map(|index| {
+                let (frame, bobber) = self.render(index);
+                let label = FrameLabel {
+                    file: PathBuf::from(format!("{index:06}.png")),
+                    bobber,
+                };
+                (frame, label)
+            })
+            .unzip();
+        let labels = Labels {
+            fps: self.params.fps,
+            frames: labels,
+            bite_frame: self.bite_frame(),
+        };
+        (labels, frames)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        profile::Profile,
+        recog::{evaluate, BiteOutcome, Brain, ColorDetector, ColorLut, Dataset, Report},
+    };
+
+    /// Short casts where a fish surely bites, at a size that keeps the tests quick.
+    fn params() -> SceneParams {
+        SceneParams {
+            width: 320,
+            height: 180,
+            duration: 5.5,
+            bite: (4.0, 4.5),
+            bite_chance: 1.0,
+            ..SceneParams::default()
+        }
+    }
+
+    #[test]
+    fn the_same_seed_renders_the_same_frames() {
+        let a = SceneGenerator::new(params(), 7);
+        let b = SceneGenerator::new(params(), 7);
+        let other = SceneGenerator::new(params(), 8);
+        assert_eq!(a.bite_frame(), b.bite_frame());
+        for index in [0, 40, a.bite_frame().unwrap(), a.frame_count() - 1] {
+            assert_eq!(a.render(index), b.render(index), "frame {index}");
+            assert_ne!(a.render(index).0, other.render(index).0, "frame {index}");
+        }
+    }
+
+    #[test]
+    fn generated_bobbers_and_bites_are_found() {
+        let dir = std::env::temp_dir().join(format!("fischer-synth-{}", std::process::id()));
+        let profile = Profile::default();
+        let mut report = Report::default();
+        for seed in 0..2 {
+            let (labels, frames) = SceneGenerator::new(params(), seed).generate();
+            let path = dir.join(format!("{seed:03}"));
+            Dataset::save(&path, &labels, &frames).unwrap();
+            let detector =
+                ColorDetector::new(ColorLut::new(&profile.thresholds), profile.scale.bobber);
+            let brain = Brain::new(&profile, Box::new(detector)).unwrap();
+            evaluate(&Dataset::open(path).unwrap(), &profile, brain, &mut report).unwrap();
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+        // The bobber is hard to see while it's pulled under, which costs a few frames
+        let recall = report.recall().unwrap();
+        assert!(recall >= 0.8, "recall {recall:.3}\n{report}");
+        let precision = report.precision().unwrap();
+        assert!(precision >= 0.95, "precision {precision:.3}\n{report}");
+        for (seed, bite) in report.bites.iter().enumerate() {
+            assert!(
+                matches!(bite, BiteOutcome::Hit { .. }),
+                "seed {seed}: {bite:?}\n{report}"
+            );
+        }
+    }
+}
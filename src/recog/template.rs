@@ -0,0 +1,177 @@
+use std::path::Path;
+
+use eyre::{ensure, Context};
+use image::{
+    imageops::{self, FilterType},
+    GrayImage, RgbImage,
+};
+use rayon::prelude::*;
+
+use super::Rect;
+
+/// Height templates are shrunk to before searching for them, in px.
+const MATCH_HEIGHT: f64 = 24.0;
+
+/// How a [`Template`] is compared with the frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Measure {
+    /// One minus the mean absolute difference of their brightness.
+    Difference,
+    /// The normalised cross-correlation of their brightness, which ignores how bright and
+    /// contrasty the frame is.
+    Correlation,
+}
+
+/// A picture of part of the game's screen to look for in frames.
+#[derive(Debug, Clone)]
+pub struct Template {
+    image: GrayImage,
+    /// Height of the frame the picture was cut from, in px.
+    frame_height: u32,
+}
+impl Template {
+    /// Loads the picture at `path`, cut from a frame `frame_height` px high.
+    pub fn load(path: impl AsRef<Path>, frame_height: u32) -> eyre::Result<Self> {
+        let path = path.as_ref();
+        ensure!(
+            !path.as_os_str().is_empty(),
+            "A template needs the path of a picture"
+        );
+        let image = image::open(path)
+            .wrap_err_with(|| format!("Failed to load template {}", path.display()))?
+            .into_luma8();
+        Ok(Self {
+            image,
+            frame_height,
+        })
+    }
+    /// The best similarity from 0 to 1 of the template anywhere within `area` of `frame`,
+    /// compared by `measure`.
+    ///
+    /// The template is resized along with the frame height, then both are shrunk so the
+    /// template is a few dozen pixels high. That's plenty to recognise a piece of UI and
+    /// keeps the exhaustive search cheap, as long as `area` isn't much bigger than the
+    /// template.
+    pub fn similarity(&self, frame: &RgbImage, area: Rect, measure: Measure) -> f64 {
+        let area = area.clamp(frame.width(), frame.height());
+        if area.area() == 0 {
+            return 0.0;
+        }
+        let scale = frame.height() as f64 / self.frame_height as f64;
+        let shrink = (MATCH_HEIGHT / (self.image.height() as f64 * scale)).min(1.0);
+        let resize = |img: &GrayImage, factor: f64| {
+            let w = (img.width() as f64 * factor).round().max(1.0) as u32;
+            let h = (img.height() as f64 * factor).round().max(1.0) as u32;
+            imageops::resize(img, w, h, FilterType::Triangle)
+        };
+        let crop = imageops::crop_imm(frame, area.x, area.y, area.width, area.height).to_image();
+        let region = resize(&imageops::grayscale(&crop), shrink);
+        let template = resize(&self.image, scale * shrink);
+        let (tw, th) = template.dimensions();
+        if tw > region.width() || th > region.height() {
+            return 0.0;
+        }
+        let (tw, th, rw) = (tw as usize, th as usize, region.width() as usize);
+        let n = (tw * th) as f64;
+        let mean = template.as_raw().iter().map(|&p| p as f64).sum::<f64>() / n;
+        let centred: Vec<f64> = template.as_raw().iter().map(|&p| p as f64 - mean).collect();
+        let energy = centred.iter().map(|c| c * c).sum::<f64>();
+        let score = |x: usize, y: usize| {
+            let rows = (0..th).map(|ty| {
+                let start = (y + ty) * rw + x;
+                (
+                    &region.as_raw()[start..start + tw],
+                    &template.as_raw()[ty * tw..(ty + 1) * tw],
+                    &centred[ty * tw..(ty + 1) * tw],
+                )
+            });
+            match measure {
+                Measure::Difference => {
+                    let total: u64 = rows
+                        .flat_map(|(r, t, _)| r.iter().zip(t))
+                        .map(|(r, t)| r.abs_diff(*t) as u64)
+                        .sum();
+                    1.0 - total as f64 / n / 255.0
+                }
+                Measure::Correlation => {
+                    let (mut sum, mut squares, mut product) = (0.0, 0.0, 0.0);
+                    for (r, c) in rows.flat_map(|(r, _, c)| r.iter().zip(c)) {
+                        let r = *r as f64;
+                        sum += r;
+                        squares += r * r;
+                        product += r * c;
+                    }
+                    let variance = squares - sum * sum / n;
+                    if energy < n || variance < n {
+                        // A flat template or window has no pattern to correlate, so only
+                        // its brightness can be compared
+                        return (1.0 - (sum / n - mean).abs() / 255.0)
+                            * f64::from(energy < n && variance < n);
+                    }
+                    product / (energy * variance).sqrt()
+                }
+            }
+        };
+        (0..=region.height() as usize - th)
+            .into_par_iter()
+            .map(|y| (0..=rw - tw).map(|x| score(x, y)).fold(0.0, f64::max))
+            .reduce(|| 0.0, f64::max)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{Luma, Rgb};
+
+    use super::*;
+
+    /// A checkerboard of 4 px squares, a pattern to look for.
+    fn checkers(size: u32) -> GrayImage {
+        GrayImage::from_fn(size, size, |x, y| {
+            Luma([if (x / 4 + y / 4) % 2 == 0 { 40 } else { 200 }])
+        })
+    }
+
+    #[test]
+    fn finds_the_template_where_it_is() {
+        let template = Template {
+            image: checkers(16),
+            frame_height: 100,
+        };
+        let mut frame = RgbImage::from_pixel(100, 100, Rgb([120, 120, 120]));
+        for (x, y, p) in checkers(16).enumerate_pixels() {
+            frame.put_pixel(60 + x, 30 + y, Rgb([p[0]; 3]));
+        }
+        let around = Rect::new(50, 20, 40, 40);
+        let elsewhere = Rect::new(0, 50, 40, 40);
+        for measure in [Measure::Difference, Measure::Correlation] {
+            let found = template.similarity(&frame, around, measure);
+            assert!(found > 0.99, "{measure:?} {found}");
+            let missing = template.similarity(&frame, elsewhere, measure);
+            assert!(missing < 0.9, "{measure:?} {missing}");
+        }
+    }
+
+    #[test]
+    fn correlation_ignores_the_brightness_of_the_frame() {
+        let template = Template {
+            image: checkers(16),
+            frame_height: 32,
+        };
+        // The same pattern, much darker and flatter than in the screenshot
+        let frame = RgbImage::from_fn(32, 32, |x, y| {
+            Rgb([if (x / 4 + y / 4) % 2 == 0 { 10 } else { 50 }; 3])
+        });
+        let area = Rect::new(0, 0, 32, 32);
+        let correlation = template.similarity(&frame, area, Measure::Correlation);
+        assert!(correlation > 0.99, "{correlation}");
+        let difference = template.similarity(&frame, area, Measure::Difference);
+        assert!(difference < 0.7, "{difference}");
+    }
+
+    #[test]
+    fn loading_needs_a_path() {
+        let error = Template::load("", 1080).unwrap_err();
+        assert!(error.to_string().contains("needs the path"), "{error}");
+    }
+}
@@ -0,0 +1,258 @@
+use std::time::Instant;
+
+use serde::{Deserialize, Serialize};
+
+/// Tuning of the [`Tracker`]'s constant-velocity model.
+///
+/// Distances are in frame heights, like the positions the tracker is fed.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TrackerParams {
+    /// Standard deviation of the unmodelled acceleration of the bobber, in frame heights/s².
+    pub process_noise: f64,
+    /// Standard deviation of a single centroid measurement, in frame heights.
+    pub measurement_noise: f64,
+    /// Measurements further than this many standard deviations from the prediction are rejected.
+    pub gate: f64,
+    /// After this many consecutive rejections the track restarts at the latest measurement.
+    pub max_rejections: u32,
+}
+impl Default for TrackerParams {
+    fn default() -> Self {
+        Self {
+            process_noise: 0.04,
+            measurement_noise: 0.002,
+            gate: 4.0,
+            max_rejections: 5,
+        }
+    }
+}
+
+/// What the [`Tracker`] did with a measurement.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrackUpdate {
+    /// There was no track yet, it was started at the measurement.
+    Started,
+    /// The measurement was plausible and has been fused into the estimate.
+    Accepted,
+    /// The measurement jumped too far from the prediction and was ignored.
+    Rejected,
+    /// Too many measurements in a row were rejected, the track was restarted at this one.
+    Restarted,
+}
+
+/// Position and velocity along a single axis, with their covariance.
+#[derive(Debug, Clone, Copy)]
+struct Axis {
+    pos: f64,
+    vel: f64,
+    cov: [[f64; 2]; 2],
+}
+impl Axis {
+    fn new(pos: f64, params: &TrackerParams) -> Self {
+        let r = params.measurement_noise.powi(2);
+        Self {
+            pos,
+            vel: 0.0,
+            cov: [[r, 0.0], [0.0, (params.measurement_noise * 10.0).powi(2)]],
+        }
+    }
+    fn predict(&mut self, dt: f64, q: f64) {
+        let [[p00, p01], [p10, p11]] = self.cov;
+        self.pos += self.vel * dt;
+        self.cov = [
+            [
+                p00 + dt * (p10 + p01) + dt * dt * p11 + q * dt.powi(4) / 4.0,
+                p01 + dt * p11 + q * dt.powi(3) / 2.0,
+            ],
+            [p10 + dt * p11 + q * dt.powi(3) / 2.0, p11 + q * dt * dt],
+        ];
+    }
+    /// Residual of `z` against the prediction and its variance.
+    fn innovation(&self, z: f64, r: f64) -> (f64, f64) {
+        (z - self.pos, self.cov[0][0] + r)
+    }
+    fn correct(&mut self, residual: f64, variance: f64) {
+        let [[p00, p01], [p10, p11]] = self.cov;
+        let k0 = p00 / variance;
+        let k1 = p10 / variance;
+        self.pos += k0 * residual;
+        self.vel += k1 * residual;
+        self.cov = [
+            [(1.0 - k0) * p00, (1.0 - k0) * p01],
+            [p10 - k1 * p00, p11 - k1 * p01],
+        ];
+    }
+}
+
+/// Kalman filter following the bobber's position and velocity across frames.
+///
+/// Each axis is modelled independently with constant velocity. Measurements that land
+/// outside the gate are rejected so a single misdetection can't drag the estimate away.
+#[derive(Debug, Clone)]
+pub struct Tracker {
+    params: TrackerParams,
+    axes: Option<([Axis; 2], Instant)>,
+    rejections: u32,
+    innovation: [f64; 2],
+    distance: f64,
+}
+impl Tracker {
+    pub fn new(params: TrackerParams) -> Self {
+        Self {
+            params,
+            axes: None,
+            rejections: 0,
+            innovation: [0.0; 2],
+            distance: 0.0,
+        }
+    }
+    /// Forgets the current track.
+    pub fn reset(&mut self) {
+        *self = Self::new(self.params);
+    }
+    /// Feeds the measured bobber position `pos` observed at `now` into the filter.
+    ///
+    /// Positions are expected in frame heights, see [`FrameScale`](super::FrameScale).
+    pub fn update(&mut self, pos: [f64; 2], now: Instant) -> TrackUpdate {
+        let Some((mut axes, last)) = self.axes else {
+            self.start(pos, now);
+            return TrackUpdate::Started;
+        };
+        let dt = now.saturating_duration_since(last).as_secs_f64();
+        let q = self.params.process_noise.powi(2);
+        let r = self.params.measurement_noise.powi(2);
+        for axis in &mut axes {
+            axis.predict(dt, q);
+        }
+        let innovations = [axes[0].innovation(pos[0], r), axes[1].innovation(pos[1], r)];
+        self.innovation = innovations.map(|(residual, _)| residual);
+        self.distance = innovations
+            .iter()
+            .map(|(residual, variance)| residual * residual / variance)
+            .sum::<f64>()
+            .sqrt();
+
+        if self.distance > self.params.gate {
+            self.rejections += 1;
+            if self.rejections > self.params.max_rejections {
+                self.start(pos, now);
+                return TrackUpdate::Restarted;
+            }
+            self.axes = Some((axes, now));
+            return TrackUpdate::Rejected;
+        }
+        for (axis, (residual, variance)) in axes.iter_mut().zip(innovations) {
+            axis.correct(residual, variance);
+        }
+        self.rejections = 0;
+        self.axes = Some((axes, now));
+        TrackUpdate::Accepted
+    }
+    fn start(&mut self, pos: [f64; 2], now: Instant) {
+        self.axes = Some((pos.map(|p| Axis::new(p, &self.params)), now));
+        self.rejections = 0;
+        self.innovation = [0.0; 2];
+        self.distance = 0.0;
+    }
+    /// The filtered position estimate, in frame heights.
+    pub fn position(&self) -> Option<[f64; 2]> {
+        self.axes.map(|(axes, _)| axes.map(|a| a.pos))
+    }
+    /// Distance in frame heights between the last measurement and the prediction it was checked against.
+    pub fn innovation(&self) -> f64 {
+        self.innovation[0].hypot(self.innovation[1])
+    }
+    /// The last innovation in units of its expected standard deviation.
+    ///
+    /// A sudden spike while the bobber is otherwise still is a good sign of a bite.
+    pub fn normalized_innovation(&self) -> f64 {
+        self.distance
+    }
+}
+impl Default for Tracker {
+    fn default() -> Self {
+        Self::new(TrackerParams::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    const FRAME: Duration = Duration::from_millis(50);
+
+    /// A tracker that has followed a bobber resting at `pos` for a second.
+    fn settled(pos: [f64; 2], start: Instant) -> (Tracker, Instant) {
+        let mut tracker = Tracker::default();
+        assert_eq!(tracker.update(pos, start), TrackUpdate::Started);
+        let mut now = start;
+        for _ in 0..20 {
+            now += FRAME;
+            assert_eq!(tracker.update(pos, now), TrackUpdate::Accepted);
+        }
+        (tracker, now)
+    }
+
+    #[test]
+    fn steady_and_moving_tracks_are_accepted_and_converge() {
+        let start = Instant::now();
+        let (tracker, _) = settled([0.5, 0.5], start);
+        let [x, y] = tracker.position().unwrap();
+        assert!((x - 0.5).abs() < 1e-9 && (y - 0.5).abs() < 1e-9);
+
+        // Drifting right at a tenth of the frame height per second
+        let mut tracker = Tracker::default();
+        let mut now = start;
+        tracker.update([0.5, 0.5], now);
+        let mut pos = [0.5, 0.5];
+        for _ in 0..40 {
+            now += FRAME;
+            pos[0] += 0.1 * FRAME.as_secs_f64();
+            assert_eq!(tracker.update(pos, now), TrackUpdate::Accepted);
+        }
+        let [x, y] = tracker.position().unwrap();
+        assert!((x - pos[0]).abs() < 0.002, "{x} vs {}", pos[0]);
+        assert!((y - pos[1]).abs() < 1e-9);
+    }
+
+    #[test]
+    fn a_single_outlier_is_rejected_without_moving_the_estimate() {
+        let (mut tracker, now) = settled([0.5, 0.5], Instant::now());
+        let before = tracker.position();
+        let update = tracker.update([0.9, 0.1], now + FRAME);
+        assert_eq!(update, TrackUpdate::Rejected);
+        assert_eq!(tracker.position(), before);
+        // The track carries on where it was
+        let update = tracker.update([0.5, 0.5], now + FRAME * 2);
+        assert_eq!(update, TrackUpdate::Accepted);
+    }
+
+    #[test]
+    fn too_many_outliers_in_a_row_restart_the_track() {
+        let (mut tracker, mut now) = settled([0.5, 0.5], Instant::now());
+        let moved = [0.8, 0.3];
+        for _ in 0..TrackerParams::default().max_rejections {
+            now += FRAME;
+            assert_eq!(tracker.update(moved, now), TrackUpdate::Rejected);
+        }
+        now += FRAME;
+        assert_eq!(tracker.update(moved, now), TrackUpdate::Restarted);
+        assert_eq!(tracker.position(), Some(moved));
+        now += FRAME;
+        assert_eq!(tracker.update(moved, now), TrackUpdate::Accepted);
+    }
+
+    #[test]
+    fn normalized_innovation_spikes_on_a_jump() {
+        let (mut tracker, now) = settled([0.5, 0.5], Instant::now());
+        // Still water stays well inside a standard deviation
+        assert!(tracker.normalized_innovation() < 0.1);
+        // A plunge of a twentieth of the frame height
+        tracker.update([0.5, 0.55], now + FRAME);
+        assert!(tracker.normalized_innovation() > TrackerParams::default().gate);
+        assert!((tracker.innovation() - 0.05).abs() < 1e-9);
+    }
+}
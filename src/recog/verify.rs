@@ -0,0 +1,190 @@
+use std::{fmt, path::PathBuf};
+
+use image::{imageops, GrayImage, RgbImage};
+use serde::{Deserialize, Serialize};
+
+use super::{Measure, RelativeRect, Template};
+
+/// How a cast ended after reeling in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CatchOutcome {
+    /// The loot showed up.
+    Caught,
+    /// There was a fish but it didn't end up in the bags. The bobber was already gone when
+    /// clicking, too late, or it stayed where it was because the click landed on nothing.
+    Missed,
+    /// The click pulled the bobber out without a fish on it.
+    FalseAlarm,
+}
+impl fmt::Display for CatchOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CatchOutcome::Caught => write!(f, "caught"),
+            CatchOutcome::Missed => write!(f, "missed"),
+            CatchOutcome::FalseAlarm => write!(f, "false alarm"),
+        }
+    }
+}
+
+/// Tuning of the [`LootDetector`] and [`CatchCheck`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LootParams {
+    /// Where the loot window or the loot message shows up.
+    pub region: RelativeRect,
+    /// Screenshot of the loot window to look for in the region, matched by its pattern so the
+    /// scene showing through the window doesn't matter. Without one, any big enough change of
+    /// the region counts as loot.
+    pub template: Option<PathBuf>,
+    /// Height of the frames the template was cut from, in px.
+    pub template_frame_height: u32,
+    /// Similarity from 0 to 1 the template has to reach somewhere in the region.
+    pub match_threshold: f64,
+    /// Mean change of the region from before the click that counts as loot, from 0 to 255.
+    pub min_change: f64,
+    /// Frames in a row without the bobber after which it counts as gone.
+    pub gone_frames: u32,
+}
+impl Default for LootParams {
+    fn default() -> Self {
+        Self {
+            // The chat, where "You receive loot" appears when looting automatically
+            region: RelativeRect {
+                x: 0.0,
+                y: 0.6,
+                width: 0.35,
+                height: 0.35,
+            },
+            template: None,
+            template_frame_height: 1080,
+            match_threshold: 0.9,
+            min_change: 12.0,
+            gone_frames: 3,
+        }
+    }
+}
+
+/// Looks for the loot UI appearing after a click.
+#[derive(Debug, Clone)]
+pub struct LootDetector {
+    params: LootParams,
+    template: Option<Template>,
+    /// The region as it looked before the click.
+    baseline: Option<GrayImage>,
+}
+impl LootDetector {
+    pub fn new(params: LootParams) -> eyre::Result<Self> {
+        let template = match &params.template {
+            Some(path) => Some(Template::load(path, params.template_frame_height)?),
+            None => None,
+        };
+        Ok(Self {
+            params,
+            template,
+            baseline: None,
+        })
+    }
+    pub fn params(&self) -> &LootParams {
+        &self.params
+    }
+    fn region(&self, frame: &RgbImage) -> GrayImage {
+        let rect = self.params.region.to_px(frame.width(), frame.height());
+        let crop = imageops::crop_imm(frame, rect.x, rect.y, rect.width, rect.height).to_image();
+        imageops::grayscale(&crop)
+    }
+    /// Remembers how the region looks while still waiting for the bite.
+    pub fn remember(&mut self, frame: &RgbImage) {
+        self.baseline = Some(self.region(frame));
+    }
+    /// Whether the loot UI is showing in `frame`.
+    pub fn appeared(&self, frame: &RgbImage) -> bool {
+        if let Some(template) = &self.template {
+            let area = self.params.region.to_px(frame.width(), frame.height());
+            return template.similarity(frame, area, Measure::Correlation)
+                >= self.params.match_threshold;
+        }
+        let region = self.region(frame);
+        self.baseline.as_ref().is_some_and(|baseline| {
+            baseline.dimensions() == region.dimensions()
+                && mean_difference(baseline, &region) >= self.params.min_change
+        })
+    }
+}
+
+/// Mean absolute difference of two equally sized images.
+fn mean_difference(a: &GrayImage, b: &GrayImage) -> f64 {
+    let total: u64 = a
+        .as_raw()
+        .iter()
+        .zip(b.as_raw())
+        .map(|(a, b)| a.abs_diff(*b) as u64)
+        .sum();
+    total as f64 / a.as_raw().len().max(1) as f64
+}
+
+/// Follows what happens after the click to tell how the cast ended.
+#[derive(Debug, Clone)]
+pub struct CatchCheck {
+    bobber_at_click: bool,
+    gone_frames: u32,
+    missing: u32,
+    bobber_gone: bool,
+    loot: bool,
+}
+impl CatchCheck {
+    /// Starts checking a click made while the bobber was `bobber_at_click` visible.
+    pub fn new(bobber_at_click: bool, gone_frames: u32) -> Self {
+        Self {
+            bobber_at_click,
+            gone_frames,
+            missing: 0,
+            bobber_gone: false,
+            loot: false,
+        }
+    }
+    /// Records a frame after the click.
+    pub fn observe(&mut self, bobber_visible: bool, loot: bool) {
+        self.missing = if bobber_visible { 0 } else { self.missing + 1 };
+        self.bobber_gone |= self.missing >= self.gone_frames;
+        self.loot |= loot;
+    }
+    pub fn outcome(&self) -> CatchOutcome {
+        if self.loot {
+            CatchOutcome::Caught
+        } else if self.bobber_at_click && self.bobber_gone {
+            CatchOutcome::FalseAlarm
+        } else {
+            CatchOutcome::Missed
+        }
+    }
+}
+
+/// Running count of how the casts ended.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CatchStats {
+    pub caught: u32,
+    pub missed: u32,
+    pub false_alarms: u32,
+}
+impl CatchStats {
+    pub fn record(&mut self, outcome: CatchOutcome) {
+        match outcome {
+            CatchOutcome::Caught => self.caught += 1,
+            CatchOutcome::Missed => self.missed += 1,
+            CatchOutcome::FalseAlarm => self.false_alarms += 1,
+        }
+    }
+}
+impl fmt::Display for CatchStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let total = self.caught + self.missed + self.false_alarms;
+        write!(
+            f,
+            "{} caught, {} missed, {} false alarms ({:.0}% of {total} reels)",
+            self.caught,
+            self.missed,
+            self.false_alarms,
+            self.caught as f64 * 100.0 / total.max(1) as f64
+        )
+    }
+}
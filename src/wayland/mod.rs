@@ -1,4 +1,4 @@
-use crate::control::{Controller, Eyes, GuiContext};
+use crate::control::{Controller, Eyes, GuiContext, WindowParams};
 use std::process::Command;
 use std::str;
 
@@ -21,7 +21,7 @@ impl GuiContext for WaylandContext {
     type Controller = WaylandController;
     type Eyes = WaylandEyes;
 
-    fn from_window_name(_: &str) -> eyre::Result<Self> {
+    fn from_window_name(_: &str, _: &WindowParams) -> eyre::Result<Self> {
         // Use slurp to select a region during context initialization
         let slurp_output = Command::new("slurp").output()?;
         let region = str::from_utf8(&slurp_output.stdout)?.trim();
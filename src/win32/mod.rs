@@ -1,7 +1,7 @@
 mod bitmap;
 use self::bitmap::Bitmap;
 use crate::{
-    control::{Controller, Eyes, GuiContext, ToBrain, ToController},
+    control::{Controller, Eyes, GuiContext, ToBrain, ToController, WindowParams},
     util::{sync_duplex, SyncDuplex},
 };
 use bitflags::bitflags;
@@ -409,7 +409,7 @@ impl GuiContext for Win32Context {
     type Controller = Win32Controller;
     type Eyes = Win32Eyes;
 
-    fn from_window_name(name: &str) -> eyre::Result<Self> {
+    fn from_window_name(name: &str, _: &WindowParams) -> eyre::Result<Self> {
         let cstr = CString::new(name)?;
         let hwnd = unsafe { FindWindowA(PCSTR::null(), PCSTR::from_raw(cstr.as_ptr() as *const _)) };
         if hwnd.0 == 0 {
@@ -0,0 +1,218 @@
+use std::fmt;
+
+use eyre::{bail, Context};
+use regex::Regex;
+
+/// What the window server knows about a window, as far as finding the game is concerned.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WindowInfo {
+    pub id: u64,
+    pub title: Option<String>,
+    /// Instance and class name, like `["wow.exe", "Wow.exe"]`.
+    pub class: Vec<String>,
+    pub pid: Option<u32>,
+    /// Whether the window is mapped and can be seen.
+    pub viewable: bool,
+}
+impl fmt::Display for WindowInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:#x} {:?}",
+            self.id,
+            self.title.as_deref().unwrap_or("")
+        )?;
+        if !self.class.is_empty() {
+            write!(f, " class {}", self.class.join("/"))?;
+        }
+        if let Some(pid) = self.pid {
+            write!(f, " pid {pid}")?;
+        }
+        if !self.viewable {
+            write!(f, " (hidden)")?;
+        }
+        Ok(())
+    }
+}
+
+/// How to recognise the game's window.
+///
+/// Parsed from the window name given on the command line: `re:<regex>` matches titles
+/// against a regular expression, `~<text>` titles containing the text, `class:<name>` the
+/// instance or class name, `pid:<pid>` the owning process and anything else the exact title.
+#[derive(Debug, Clone)]
+pub enum WindowQuery {
+    Exact(String),
+    Substring(String),
+    Regex(Regex),
+    Class(String),
+    Pid(u32),
+}
+impl WindowQuery {
+    pub fn parse(query: &str) -> eyre::Result<Self> {
+        Ok(if let Some(pattern) = query.strip_prefix("re:") {
+            let regex = Regex::new(pattern)
+                .wrap_err_with(|| format!("Invalid window title pattern {pattern:?}"))?;
+            WindowQuery::Regex(regex)
+        } else if let Some(text) = query.strip_prefix('~') {
+            WindowQuery::Substring(text.to_owned())
+        } else if let Some(class) = query.strip_prefix("class:") {
+            WindowQuery::Class(class.to_owned())
+        } else if let Some(pid) = query.strip_prefix("pid:") {
+            let pid = pid
+                .parse()
+                .wrap_err_with(|| format!("Invalid window pid {pid:?}"))?;
+            WindowQuery::Pid(pid)
+        } else {
+            WindowQuery::Exact(query.to_owned())
+        })
+    }
+    pub fn matches(&self, window: &WindowInfo) -> bool {
+        let title = window.title.as_deref();
+        match self {
+            WindowQuery::Exact(name) => title == Some(name.as_str()),
+            WindowQuery::Substring(text) => title.is_some_and(|t| t.contains(text.as_str())),
+            WindowQuery::Regex(regex) => title.is_some_and(|t| regex.is_match(t)),
+            WindowQuery::Class(class) => window.class.iter().any(|c| c == class),
+            WindowQuery::Pid(pid) => window.pid == Some(*pid),
+        }
+    }
+    /// The one window in `windows` the query matches.
+    ///
+    /// When it matches several, the viewable ones win, since games and toolkits like to
+    /// keep hidden helper windows with the same title around. Anything still ambiguous is
+    /// an error listing the candidates.
+    pub fn find<'a>(&self, windows: &'a [WindowInfo]) -> eyre::Result<&'a WindowInfo> {
+        let matched: Vec<_> = windows.iter().filter(|w| self.matches(w)).collect();
+        let viewable: Vec<_> = matched.iter().copied().filter(|w| w.viewable).collect();
+        match (matched.as_slice(), viewable.as_slice()) {
+            ([], _) => bail!("No window matches {self}"),
+            ([window], _) | (_, [window]) => Ok(window),
+            _ => {
+                let candidates: Vec<_> = matched.iter().map(|w| format!("  {w}")).collect();
+                bail!(
+                    "{} windows match {self}, be more specific:\n{}",
+                    matched.len(),
+                    candidates.join("\n")
+                )
+            }
+        }
+    }
+}
+impl fmt::Display for WindowQuery {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WindowQuery::Exact(name) => write!(f, "title {name:?}"),
+            WindowQuery::Substring(text) => write!(f, "a title containing {text:?}"),
+            WindowQuery::Regex(regex) => write!(f, "title pattern {:?}", regex.as_str()),
+            WindowQuery::Class(class) => write!(f, "class {class:?}"),
+            WindowQuery::Pid(pid) => write!(f, "pid {pid}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn window(id: u64, title: &str, class: &[&str], pid: u32, viewable: bool) -> WindowInfo {
+        WindowInfo {
+            id,
+            title: Some(title.to_owned()),
+            class: class.iter().map(|c| c.to_string()).collect(),
+            pid: Some(pid),
+            viewable,
+        }
+    }
+
+    #[test]
+    fn prefixes_pick_the_kind_of_query() {
+        let parse = |query| WindowQuery::parse(query).unwrap();
+        assert!(
+            matches!(parse("World of Warcraft"), WindowQuery::Exact(t) if t == "World of Warcraft")
+        );
+        assert!(matches!(parse("~Warcraft"), WindowQuery::Substring(t) if t == "Warcraft"));
+        assert!(
+            matches!(parse("re:^World.*$"), WindowQuery::Regex(r) if r.as_str() == "^World.*$")
+        );
+        assert!(matches!(parse("class:wow.exe"), WindowQuery::Class(c) if c == "wow.exe"));
+        assert!(matches!(parse("pid:1234"), WindowQuery::Pid(1234)));
+        // Only the start counts
+        assert!(matches!(parse("My pid:1"), WindowQuery::Exact(t) if t == "My pid:1"));
+    }
+
+    #[test]
+    fn bad_patterns_and_pids_are_errors() {
+        let error = WindowQuery::parse("re:(").unwrap_err();
+        assert!(
+            error.to_string().contains("Invalid window title pattern"),
+            "{error:#}"
+        );
+        let error = WindowQuery::parse("pid:wow").unwrap_err();
+        assert!(
+            error.to_string().contains("Invalid window pid \"wow\""),
+            "{error:#}"
+        );
+    }
+
+    #[test]
+    fn queries_match_their_part_of_the_window() {
+        let wow = window(1, "World of Warcraft", &["wow.exe", "Wow.exe"], 42, true);
+        let matches = |query| WindowQuery::parse(query).unwrap().matches(&wow);
+        assert!(matches("World of Warcraft"));
+        assert!(!matches("World of"));
+        assert!(matches("~of War"));
+        assert!(!matches("~of war"));
+        assert!(matches("re:^World .* Warcraft$"));
+        assert!(!matches("re:^Warcraft"));
+        assert!(matches("class:Wow.exe"));
+        assert!(!matches("class:World of Warcraft"));
+        assert!(matches("pid:42"));
+        assert!(!matches("pid:43"));
+
+        let untitled = WindowInfo {
+            title: None,
+            ..wow.clone()
+        };
+        assert!(!WindowQuery::parse("~").unwrap().matches(&untitled));
+        assert!(WindowQuery::parse("pid:42").unwrap().matches(&untitled));
+    }
+
+    #[test]
+    fn viewable_windows_win_over_hidden_ones() {
+        let windows = [
+            window(1, "World of Warcraft", &[], 42, false),
+            window(2, "World of Warcraft", &[], 42, true),
+            window(3, "Launcher", &[], 7, true),
+        ];
+        let query = WindowQuery::parse("World of Warcraft").unwrap();
+        assert_eq!(query.find(&windows).unwrap().id, 2);
+        // A single match is taken even when hidden
+        let query = WindowQuery::parse("pid:42").unwrap();
+        assert_eq!(query.find(&windows[..1]).unwrap().id, 1);
+
+        let error = WindowQuery::parse("Nothing")
+            .unwrap()
+            .find(&windows)
+            .unwrap_err();
+        assert_eq!(error.to_string(), "No window matches title \"Nothing\"");
+    }
+
+    #[test]
+    fn several_matches_are_an_error_listing_them() {
+        let windows = [
+            window(0x10, "World of Warcraft", &[], 42, true),
+            window(0x20, "World of Warcraft", &[], 43, true),
+            window(0x30, "World of Warcraft", &[], 44, false),
+        ];
+        let query = WindowQuery::parse("~Warcraft").unwrap();
+        let error = query.find(&windows).unwrap_err().to_string();
+        assert_eq!(
+            error,
+            "3 windows match a title containing \"Warcraft\", be more specific:\n  \
+             0x10 \"World of Warcraft\" pid 42\n  \
+             0x20 \"World of Warcraft\" pid 43\n  \
+             0x30 \"World of Warcraft\" pid 44 (hidden)"
+        );
+    }
+}
@@ -0,0 +1,101 @@
+use x11rb::{
+    connection::Connection,
+    errors::ReplyError,
+    protocol::xproto::{Atom, AtomEnum, ConnectionExt, MapState, Window},
+};
+
+use crate::window::{WindowInfo, WindowQuery};
+
+/// Finds the window described by `name` among all windows of `conn`.
+///
+/// See [`WindowQuery`] for the ways to describe it.
+pub fn find_window(conn: &impl Connection, name: &str) -> eyre::Result<Window> {
+    let query = WindowQuery::parse(name)?;
+    let windows = list_windows(conn)?;
+    Ok(query.find(&windows)?.id as Window)
+}
+
+/// Every window below the root windows that has a title, class or pid.
+///
+/// The whole tree is walked rather than just the children of the root, since window managers
+/// reparent the clients into frames of their own.
+fn list_windows(conn: &impl Connection) -> Result<Vec<WindowInfo>, ReplyError> {
+    let atoms = Atoms::new(conn)?;
+    let mut windows = Vec::new();
+    let mut stack: Vec<_> = conn
+        .setup()
+        .roots
+        .iter()
+        .map(|screen| screen.root)
+        .collect();
+    while let Some(window) = stack.pop() {
+        // Windows can vanish while walking the tree, which is no reason to fail
+        let Ok(tree) = conn.query_tree(window)?.reply() else {
+            continue;
+        };
+        stack.extend(tree.children);
+        let Ok(info) = atoms.describe(conn, window) else {
+            continue;
+        };
+        if info.title.is_some() || !info.class.is_empty() || info.pid.is_some() {
+            windows.push(info);
+        }
+    }
+    Ok(windows)
+}
+
+/// The properties a window is recognised by.
+struct Atoms {
+    net_wm_name: Atom,
+    net_wm_pid: Atom,
+    utf8_string: Atom,
+}
+impl Atoms {
+    fn new(conn: &impl Connection) -> Result<Self, ReplyError> {
+        let atom = |name: &[u8]| -> Result<Atom, ReplyError> {
+            Ok(conn.intern_atom(true, name)?.reply()?.atom)
+        };
+        Ok(Self {
+            net_wm_name: atom(b"_NET_WM_NAME")?,
+            net_wm_pid: atom(b"_NET_WM_PID")?,
+            utf8_string: atom(b"UTF8_STRING")?,
+        })
+    }
+    fn describe(&self, conn: &impl Connection, window: Window) -> Result<WindowInfo, ReplyError> {
+        let property = |property: Atom, kind: Atom| -> Result<Option<_>, ReplyError> {
+            if property == x11rb::NONE {
+                return Ok(None);
+            }
+            let reply = conn
+                .get_property(false, window, property, kind, 0, 4096)?
+                .reply()?;
+            Ok((reply.type_ != x11rb::NONE).then_some(reply))
+        };
+        // _NET_WM_NAME is always UTF-8, WM_NAME usually Latin-1 but nobody checks
+        let title = match property(self.net_wm_name, self.utf8_string)? {
+            Some(reply) => Some(reply),
+            None => property(AtomEnum::WM_NAME.into(), AtomEnum::ANY.into())?,
+        }
+        .map(|reply| String::from_utf8_lossy(&reply.value).into_owned());
+        let class = property(AtomEnum::WM_CLASS.into(), AtomEnum::STRING.into())?
+            .map(|reply| {
+                reply
+                    .value
+                    .split(|&b| b == 0)
+                    .filter(|name| !name.is_empty())
+                    .map(|name| String::from_utf8_lossy(name).into_owned())
+                    .collect()
+            })
+            .unwrap_or_default();
+        let pid = property(self.net_wm_pid, AtomEnum::CARDINAL.into())?
+            .and_then(|reply| reply.value32()?.next());
+        let attributes = conn.get_window_attributes(window)?.reply()?;
+        Ok(WindowInfo {
+            id: window.into(),
+            title,
+            class,
+            pid,
+            viewable: attributes.map_state == MapState::VIEWABLE,
+        })
+    }
+}
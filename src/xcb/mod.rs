@@ -0,0 +1,557 @@
+use crate::{
+    control::{Controller, GuiContext, InputMethod, ToController, WindowParams},
+    follow::{self, Capture, Change, Geometry, WindowEyes, WindowSource},
+    pixels::{self, PixelFormat},
+    recog::Rect,
+};
+
+use eyre::{ensure, ContextCompat, WrapErr};
+use image::RgbImage;
+use shm::{SetupError, ShmCapture};
+use std::{
+    os::fd::AsRawFd,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        mpsc::Receiver,
+        Arc,
+    },
+    time::Duration,
+};
+use x11rb::{
+    connection::Connection,
+    errors::{ConnectionError, ReplyError, ReplyOrIdError},
+    protocol::{
+        composite::{self, ConnectionExt as _, Redirect},
+        damage::{self, ConnectionExt as _, ReportLevel},
+        xproto::{
+            ButtonPressEvent, ChangeWindowAttributesAux, ConnectionExt as _, Drawable, EventMask,
+            ImageFormat, ImageOrder, KeyPressEvent, MapState, Pixmap, Setup, Visualid, Window,
+            BUTTON_PRESS_EVENT, BUTTON_RELEASE_EVENT, KEY_PRESS_EVENT, KEY_RELEASE_EVENT,
+            MOTION_NOTIFY_EVENT,
+        },
+        xtest::{self, ConnectionExt as _},
+        ErrorKind, Event,
+    },
+    rust_connection::RustConnection,
+    CURRENT_TIME, NONE,
+};
+
+mod lookup;
+mod shm;
+
+/// Keycode of the key that casts, the backtick on a standard keyboard.
+const CAST_KEYCODE: u8 = 49;
+
+/// Connects to the display called `name`, the default one without a name. Every thread
+/// gets a connection of its own.
+fn connect(name: Option<&str>) -> eyre::Result<RustConnection> {
+    let (conn, _) = x11rb::connect(name).wrap_err_with(|| {
+        let name = name.map_or_else(
+            || std::env::var("DISPLAY").unwrap_or_default(),
+            str::to_owned,
+        );
+        format!("Failed to open the X display {name:?}")
+    })?;
+    Ok(conn)
+}
+
+/// Whether `error` means the window or something it's drawn into is gone, which can
+/// happen at any moment.
+fn is_gone(error: &ReplyError) -> bool {
+    matches!(
+        error,
+        ReplyError::X11Error(error)
+            if matches!(error.error_kind, ErrorKind::Window | ErrorKind::Drawable)
+    )
+}
+
+/// Checks that the server of `conn` supports the extension `name`.
+fn require(conn: &impl Connection, name: &'static str) -> eyre::Result<()> {
+    ensure!(
+        conn.extension_information(name)?.is_some(),
+        "The X server doesn't support {name}"
+    );
+    Ok(())
+}
+
+#[derive(Debug, Clone)]
+pub struct XcbContext {
+    /// The game window, replaced by the eyes when the game restarts.
+    window: Arc<AtomicU32>,
+    name: String,
+    params: WindowParams,
+}
+impl GuiContext for XcbContext {
+    type Controller = XcbController;
+    type Eyes = XcbEyes;
+
+    fn from_window_name(name: &str, params: &WindowParams) -> eyre::Result<Self> {
+        let window = lookup::find_window(&connect(params.display.as_deref())?, name)?;
+        Ok(Self {
+            window: Arc::new(AtomicU32::new(window)),
+            name: name.to_owned(),
+            params: params.clone(),
+        })
+    }
+
+    fn controller(&self) -> eyre::Result<Self::Controller> {
+        XcbController::new(self.window.clone(), &self.params)
+    }
+
+    fn eyes(&self) -> eyre::Result<Self::Eyes> {
+        let source = XcbSource::new(self.window.clone(), &self.params)?;
+        XcbEyes::new(source, self.name.clone(), &self.params)
+    }
+}
+
+pub struct XcbController {
+    window: Arc<AtomicU32>,
+    conn: RustConnection,
+    /// Whether to fake device input through XTest. Otherwise events are sent to the
+    /// window.
+    xtest: bool,
+}
+
+/// The eyes, following the game window through x11rb.
+pub type XcbEyes = WindowEyes<XcbSource>;
+
+pub struct XcbSource {
+    window: Arc<AtomicU32>,
+    conn: RustConnection,
+    /// Size of the window, as of the last refresh.
+    size: [u16; 2],
+    visual: Visualid,
+    depth: u8,
+    /// Shared memory capture, set up for the current size of the window.
+    shm: Option<ShmCapture>,
+    /// Whether shared memory capture can't ever work, leaving only `GetImage`.
+    shm_failed: bool,
+    /// Whether to capture the off-screen copy of the window that Composite keeps.
+    composite: bool,
+    /// The off-screen copy of the window, once named for its current storage.
+    pixmap: Option<Pixmap>,
+    /// Damage reports of the window, when capturing only after changes.
+    damage: Option<damage::Damage>,
+}
+
+impl XcbSource {
+    pub fn new(window: Arc<AtomicU32>, params: &WindowParams) -> eyre::Result<Self> {
+        let conn = connect(params.display.as_deref())?;
+        let damage = match params.damage {
+            true => match load_damage(&conn) {
+                Ok(()) => Some(NONE),
+                Err(e) => {
+                    println!("Warning: {e:#}, capturing continuously");
+                    None
+                }
+            },
+            false => None,
+        };
+        let composite = match params.composite {
+            true => match load_composite(&conn) {
+                Ok(()) => true,
+                Err(e) => {
+                    println!("Warning: {e:#}, capturing only the visible parts of the window");
+                    false
+                }
+            },
+            false => false,
+        };
+        Ok(Self {
+            window,
+            conn,
+            size: [0, 0],
+            visual: 0,
+            depth: 0,
+            shm: None,
+            shm_failed: false,
+            composite,
+            pixmap: None,
+            damage,
+        })
+    }
+
+    /// What to capture from, the off-screen copy of the window if there's one.
+    fn drawable(&mut self) -> Result<Drawable, ReplyOrIdError> {
+        let window = self.window();
+        if !self.composite {
+            return Ok(window);
+        }
+        if let Some(pixmap) = self.pixmap {
+            return Ok(pixmap);
+        }
+        let pixmap = self.conn.generate_id()?;
+        match self
+            .conn
+            .composite_name_window_pixmap(window, pixmap)?
+            .check()
+        {
+            Ok(()) => {
+                self.pixmap = Some(pixmap);
+                Ok(pixmap)
+            }
+            Err(e) if is_gone(&e) => Ok(window),
+            Err(e @ ReplyError::X11Error(_)) => {
+                println!("Warning: {e}, capturing only the visible parts of the window");
+                self.composite = false;
+                Ok(window)
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Sets up shared memory capture of `size` bytes, unless it already is or can't work.
+    fn prepare_shm(&mut self, size: usize) -> Result<(), ConnectionError> {
+        if self.shm_failed || size == 0 || self.shm.as_ref().is_some_and(|shm| shm.size() == size) {
+            return Ok(());
+        }
+        if let Some(shm) = self.shm.take() {
+            shm.destroy(&self.conn)?;
+        }
+        match ShmCapture::new(&self.conn, size) {
+            Ok(shm) => self.shm = Some(shm),
+            Err(e @ SetupError::Unsupported(_)) => {
+                println!("Warning: {e}, capturing without shared memory");
+                self.shm_failed = true;
+            }
+            // Tried again once the window has another size
+            Err(e @ SetupError::Failed(_)) => {
+                println!("Warning: {e}, capturing this size without shared memory");
+            }
+        }
+        Ok(())
+    }
+
+    /// Captures the window, which is `size` big.
+    fn grab(&mut self, size: [u32; 2]) -> eyre::Result<RgbImage> {
+        let drawable = self.drawable()?;
+        let [width, height] = size.map(|v| v as u16);
+        let format = pixel_format(self.conn.setup(), self.visual, self.depth, width)?;
+        let bytes = format.bytes_per_line * height as usize;
+
+        self.prepare_shm(bytes)?;
+        if let Some(shm) = &mut self.shm {
+            let data = shm.capture(&self.conn, drawable, [width, height])?;
+            return pixels::to_rgb(&format, width.into(), height.into(), data);
+        }
+
+        let format_z = ImageFormat::Z_PIXMAP;
+        let image = self
+            .conn
+            .get_image(format_z, drawable, 0, 0, width, height, !0)?
+            .reply()?;
+        pixels::to_rgb(&format, width.into(), height.into(), &image.data)
+    }
+}
+impl WindowSource for XcbSource {
+    type Window = Window;
+
+    fn window(&self) -> Window {
+        self.window.load(Ordering::Relaxed)
+    }
+
+    fn find(&mut self, name: &str) -> eyre::Result<Window> {
+        lookup::find_window(&self.conn, name)
+    }
+
+    fn follow(&mut self, window: Window) -> eyre::Result<Geometry> {
+        self.window.store(window, Ordering::Relaxed);
+        let attributes = ChangeWindowAttributesAux::new().event_mask(EventMask::STRUCTURE_NOTIFY);
+        self.conn
+            .change_window_attributes(window, &attributes)?
+            .check()?;
+        if self.composite {
+            self.conn
+                .composite_redirect_window(window, Redirect::AUTOMATIC)?
+                .check()?;
+        }
+        self.forget_pixmap()?;
+        if let Some(id) = &mut self.damage {
+            *id = self.conn.generate_id()?;
+            self.conn
+                .damage_create(*id, window, ReportLevel::DELTA_RECTANGLES)?
+                .check()?;
+        }
+        self.refresh()
+    }
+
+    fn refresh(&mut self) -> eyre::Result<Geometry> {
+        let window = self.window();
+        let attributes = self.conn.get_window_attributes(window)?;
+        let geometry = self.conn.get_geometry(window)?;
+        let (attributes, geometry) = (attributes.reply()?, geometry.reply()?);
+        self.size = [geometry.width, geometry.height];
+        self.visual = attributes.visual;
+        self.depth = geometry.depth;
+        Ok(Geometry {
+            size: self.size.map(u32::from),
+            viewable: attributes.map_state == MapState::VIEWABLE,
+        })
+    }
+
+    fn next_change(&mut self) -> eyre::Result<Option<Change>> {
+        let window = self.window();
+        while let Some(event) = self.conn.poll_for_event()? {
+            let change = match event {
+                Event::DamageNotify(event) if Some(event.damage) == self.damage => {
+                    let area = event.area;
+                    let [x, y] = [area.x, area.y].map(|v| v.max(0) as u32);
+                    Change::Damaged(Rect::new(x, y, area.width.into(), area.height.into()))
+                }
+                Event::UnmapNotify(event) if event.window == window => Change::Unmapped,
+                Event::MapNotify(event) if event.window == window => Change::Mapped,
+                Event::ConfigureNotify(event) if event.window == window => {
+                    Change::Configured([event.width.into(), event.height.into()])
+                }
+                Event::DestroyNotify(event) if event.window == window => Change::Destroyed,
+                // Errors of requests nobody waited for, such as freeing the pixmap of a
+                // window that's gone
+                Event::Error(error) => {
+                    let error = ReplyError::X11Error(error);
+                    if !is_gone(&error) {
+                        return Err(error.into());
+                    }
+                    continue;
+                }
+                _ => continue,
+            };
+            return Ok(Some(change));
+        }
+        Ok(None)
+    }
+
+    fn wait(&mut self, timeout: Option<Duration>) -> eyre::Result<bool> {
+        // Events already read from the connection were handled before getting here
+        self.conn.flush()?;
+        Ok(follow::wait_readable(
+            self.conn.stream().as_raw_fd(),
+            timeout,
+        )?)
+    }
+
+    fn reports_damage(&self) -> bool {
+        self.damage.is_some()
+    }
+
+    fn clear_damage(&mut self) -> eyre::Result<()> {
+        if let Some(id) = self.damage {
+            self.conn.damage_subtract(id, NONE, NONE)?;
+        }
+        Ok(())
+    }
+
+    fn forget_pixmap(&mut self) -> eyre::Result<()> {
+        if let Some(pixmap) = self.pixmap.take() {
+            self.conn.free_pixmap(pixmap)?;
+        }
+        Ok(())
+    }
+
+    fn capture(&mut self, size: [u32; 2]) -> eyre::Result<Capture> {
+        match self.grab(size) {
+            Ok(frame) => Ok(Capture::Frame(frame)),
+            // Errors of the server only concern this frame
+            Err(e) if matches!(e.downcast_ref(), Some(ReplyError::X11Error(_))) => {
+                Ok(Capture::Refused(e))
+            }
+            Err(e) => Err(e),
+        }
+    }
+}
+impl Drop for XcbSource {
+    fn drop(&mut self) {
+        // Closing the connection also ends the redirection of the window
+        if let Some(shm) = self.shm.take() {
+            let _ = shm.destroy(&self.conn);
+        }
+        let _ = self.forget_pixmap();
+        let _ = self.conn.flush();
+    }
+}
+
+/// Checks that the server of `conn` supports DAMAGE, which has to be asked before using it.
+fn load_damage(conn: &impl Connection) -> eyre::Result<()> {
+    require(conn, damage::X11_EXTENSION_NAME)?;
+    conn.damage_query_version(1, 1)?.reply()?;
+    Ok(())
+}
+
+/// Checks that the server of `conn` supports Composite 0.2, the first version that can
+/// name window pixmaps.
+fn load_composite(conn: &impl Connection) -> eyre::Result<()> {
+    require(conn, composite::X11_EXTENSION_NAME)?;
+    let version = conn.composite_query_version(0, 4)?.reply()?;
+    let (major, minor) = (version.major_version, version.minor_version);
+    ensure!(
+        (major, minor) >= (0, 2),
+        "The X server only supports Composite {major}.{minor}, 0.2 is needed"
+    );
+    Ok(())
+}
+
+/// How the server stores pixels of `visual` at `depth`, in rows of `width` pixels.
+fn pixel_format(
+    setup: &Setup,
+    visual: Visualid,
+    depth: u8,
+    width: u16,
+) -> eyre::Result<PixelFormat> {
+    let visual = setup
+        .roots
+        .iter()
+        .flat_map(|screen| &screen.allowed_depths)
+        .flat_map(|depth| &depth.visuals)
+        .find(|candidate| candidate.visual_id == visual)
+        .with_context(|| format!("The X server lacks the visual {visual:#x} of the window"))?;
+    let format = setup
+        .pixmap_formats
+        .iter()
+        .find(|format| format.depth == depth)
+        .with_context(|| format!("The X server lacks a pixel format of depth {depth}"))?;
+    // Rows are padded to whole scanline units
+    let pad = format.scanline_pad as usize;
+    let bits_per_line = width as usize * format.bits_per_pixel as usize;
+    Ok(PixelFormat {
+        bits_per_pixel: format.bits_per_pixel.into(),
+        bytes_per_line: bits_per_line.div_ceil(pad) * pad / 8,
+        red_mask: visual.red_mask,
+        green_mask: visual.green_mask,
+        blue_mask: visual.blue_mask,
+        big_endian: setup.image_byte_order == ImageOrder::MSB_FIRST,
+    })
+}
+
+impl XcbController {
+    pub fn new(window: Arc<AtomicU32>, params: &WindowParams) -> eyre::Result<Self> {
+        let conn = connect(params.display.as_deref())?;
+        let xtest = match params.input {
+            InputMethod::SendEvent => false,
+            InputMethod::XTest => match load_xtest(&conn) {
+                Ok(()) => true,
+                Err(e) => {
+                    println!("Warning: {e:#}, sending events to the window instead");
+                    false
+                }
+            },
+        };
+        Ok(Self {
+            window,
+            conn,
+            xtest,
+        })
+    }
+
+    /// The game window, which changes when the game restarts.
+    fn window(&self) -> Window {
+        self.window.load(Ordering::Relaxed)
+    }
+
+    /// Moves the pointer through XTest to `[x, y]` of the window, returning the root
+    /// window it's on.
+    fn fake_motion(&self, [x, y]: [i32; 2]) -> Result<Window, ReplyError> {
+        let window = self.window();
+        let root = self.conn.get_geometry(window)?.reply()?.root;
+        let at = self
+            .conn
+            .translate_coordinates(window, root, x as i16, y as i16)?
+            .reply()?;
+        self.conn
+            .xtest_fake_input(
+                MOTION_NOTIFY_EVENT,
+                0,
+                CURRENT_TIME,
+                root,
+                at.dst_x,
+                at.dst_y,
+                0,
+            )?
+            .check()?;
+        Ok(root)
+    }
+
+    pub fn move_mouse_to_coordinate(&self, x: i32, y: i32) -> Result<(), ReplyError> {
+        if self.xtest {
+            self.fake_motion([x, y])?;
+            return Ok(());
+        }
+        self.conn
+            .warp_pointer(NONE, self.window(), 0, 0, 0, 0, x as i16, y as i16)?
+            .check()
+    }
+    pub fn left_click(&self, x: i32, y: i32) -> Result<(), ReplyError> {
+        if self.xtest {
+            let root = self.fake_motion([x, y])?;
+            for kind in [BUTTON_PRESS_EVENT, BUTTON_RELEASE_EVENT] {
+                self.conn
+                    .xtest_fake_input(kind, 1, CURRENT_TIME, root, 0, 0, 0)?
+                    .check()?;
+            }
+            return Ok(());
+        }
+        let window = self.window();
+        for (kind, mask) in [
+            (BUTTON_PRESS_EVENT, EventMask::BUTTON_PRESS),
+            (BUTTON_RELEASE_EVENT, EventMask::BUTTON_RELEASE),
+        ] {
+            let event = ButtonPressEvent {
+                response_type: kind,
+                detail: 1,
+                event: window,
+                event_x: x as i16,
+                event_y: y as i16,
+                same_screen: true,
+                ..Default::default()
+            };
+            self.conn.send_event(true, window, mask, event)?.check()?;
+        }
+        Ok(())
+    }
+    pub fn cast_hook(&self) -> Result<(), ReplyError> {
+        if self.xtest {
+            for kind in [KEY_PRESS_EVENT, KEY_RELEASE_EVENT] {
+                self.conn
+                    .xtest_fake_input(kind, CAST_KEYCODE, CURRENT_TIME, NONE, 0, 0, 0)?
+                    .check()?;
+            }
+            return Ok(());
+        }
+        let window = self.window();
+        for (kind, mask) in [
+            (KEY_PRESS_EVENT, EventMask::KEY_PRESS),
+            (KEY_RELEASE_EVENT, EventMask::KEY_RELEASE),
+        ] {
+            let event = KeyPressEvent {
+                response_type: kind,
+                detail: CAST_KEYCODE,
+                event: window,
+                same_screen: true,
+                ..Default::default()
+            };
+            self.conn.send_event(true, window, mask, event)?.check()?;
+        }
+        Ok(())
+    }
+}
+impl Controller for XcbController {
+    fn run(self, input: Receiver<ToController>) -> eyre::Result<()> {
+        loop {
+            let done = match input.recv()? {
+                ToController::MoveMouse([x, y]) => self.move_mouse_to_coordinate(x, y),
+                ToController::PerformClick([x, y]) => self.left_click(x, y),
+                ToController::CastHook => self.cast_hook(),
+            };
+            match done {
+                Ok(()) => {}
+                // The eyes notice and wait for the game to come back
+                Err(e) if is_gone(&e) => println!("Warning: {e}, the game window is gone"),
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+}
+/// Checks that the server of `conn` supports XTest.
+fn load_xtest(conn: &impl Connection) -> eyre::Result<()> {
+    require(conn, xtest::X11_EXTENSION_NAME)?;
+    conn.xtest_get_version(2, 2)?.reply()?;
+    Ok(())
+}
@@ -0,0 +1,141 @@
+use std::{ffi::c_void, fmt, ptr, slice};
+
+use eyre::bail;
+use x11rb::{
+    connection::Connection,
+    errors::{ConnectionError, ReplyError},
+    protocol::{
+        shm::{self, ConnectionExt},
+        xproto::{Drawable, ImageFormat},
+    },
+};
+
+/// Why shared memory capture couldn't be set up.
+#[derive(Debug)]
+pub enum SetupError {
+    /// The server can't share memory with this process at all, e.g. because it's remote.
+    Unsupported(eyre::Report),
+    /// Setting up failed for the current size of the window, it may work for another one.
+    Failed(eyre::Report),
+}
+impl fmt::Display for SetupError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SetupError::Unsupported(e) | SetupError::Failed(e) => write!(f, "{e:#}"),
+        }
+    }
+}
+
+/// Captures of a window through shared memory of the MIT-SHM extension, which spares
+/// copying every frame through the connection.
+pub struct ShmCapture {
+    segment: Segment,
+    /// The id the server knows the segment by.
+    seg: shm::Seg,
+}
+impl ShmCapture {
+    /// Sets up `size` bytes of shared memory with the server of `conn`.
+    ///
+    /// Fails for good when the extension is missing or the server can't attach the segment,
+    /// which is always the case over the network.
+    pub fn new(conn: &impl Connection, size: usize) -> Result<Self, SetupError> {
+        let unsupported = SetupError::Unsupported;
+        let supported = conn
+            .extension_information(shm::X11_EXTENSION_NAME)
+            .map_err(|e| unsupported(e.into()))?
+            .is_some();
+        if !supported {
+            return Err(unsupported(eyre::eyre!(
+                "The X server doesn't support MIT-SHM"
+            )));
+        }
+        let segment = Segment::new(size).map_err(SetupError::Failed)?;
+        let seg = conn.generate_id().map_err(|e| unsupported(e.into()))?;
+        // Servers on other machines fail to attach the segment
+        conn.shm_attach(seg, segment.id(), false)
+            .map_err(|e| unsupported(e.into()))?
+            .check()
+            .map_err(|e| {
+                unsupported(
+                    eyre::Report::new(e)
+                        .wrap_err("The X server can't attach shared memory, it's probably remote"),
+                )
+            })?;
+        Ok(Self { segment, seg })
+    }
+    pub fn size(&self) -> usize {
+        self.segment.size()
+    }
+    /// Captures all of `drawable` that fits into the segment, `width` by `height` pixels.
+    pub fn capture(
+        &mut self,
+        conn: &impl Connection,
+        drawable: Drawable,
+        [width, height]: [u16; 2],
+    ) -> Result<&[u8], ReplyError> {
+        let format = ImageFormat::Z_PIXMAP.into();
+        conn.shm_get_image(drawable, 0, 0, width, height, !0, format, self.seg, 0)?
+            .reply()?;
+        Ok(self.segment.data())
+    }
+    /// Has the server let go of the segment.
+    pub fn destroy(self, conn: &impl Connection) -> Result<(), ConnectionError> {
+        conn.shm_detach(self.seg)?;
+        conn.flush()
+    }
+}
+
+/// A System V shared memory segment, mapped into this process to let the X server write
+/// captured images into it.
+struct Segment {
+    id: i32,
+    address: *mut c_void,
+    size: usize,
+}
+impl Segment {
+    fn new(size: usize) -> eyre::Result<Self> {
+        unsafe {
+            let id = libc::shmget(libc::IPC_PRIVATE, size, libc::IPC_CREAT | 0o600);
+            if id < 0 {
+                bail!("Failed to allocate {size} bytes of shared memory");
+            }
+            let address = libc::shmat(id, ptr::null(), 0);
+            // Mark the segment for removal right away, it goes once both sides let go of it
+            libc::shmctl(id, libc::IPC_RMID, ptr::null_mut());
+            if address as isize == -1 {
+                bail!("Failed to attach shared memory");
+            }
+            Ok(Self { id, address, size })
+        }
+    }
+    fn id(&self) -> u32 {
+        self.id as u32
+    }
+    fn size(&self) -> usize {
+        self.size
+    }
+    /// The contents of the segment.
+    ///
+    /// The server only writes to it while answering a request for an image, which is done
+    /// by the time the answer arrives. Requests take the capture mutably, so the contents
+    /// don't change while borrowed.
+    fn data(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(self.address.cast(), self.size) }
+    }
+}
+impl Drop for Segment {
+    fn drop(&mut self) {
+        unsafe {
+            libc::shmdt(self.address);
+        }
+    }
+}
+
+// SAFETY: The mapping belongs to the segment alone and stays valid until it's dropped,
+// whichever thread that happens on. It's created without a key for this user only and
+// marked for removal right after mapping it, so besides this process only the server the
+// id is handed to writes to it. The server only does while answering
+// `ShmCapture::capture`, which borrows the capture mutably, so shared borrows on several
+// threads only ever read it.
+unsafe impl Send for Segment {}
+unsafe impl Sync for Segment {}
@@ -0,0 +1,69 @@
+use std::ffi::c_int;
+
+use eyre::bail;
+use x11::xlib::{self, _XDisplay, Bool, Pixmap};
+
+/// Redirect mode in which the server keeps drawing the window on screen by itself.
+const REDIRECT_AUTOMATIC: c_int = 0;
+
+// The x11 crate has no bindings of libXcomposite
+#[link(name = "Xcomposite")]
+extern "C" {
+    fn XCompositeQueryExtension(
+        display: *mut _XDisplay,
+        event_base: *mut c_int,
+        error_base: *mut c_int,
+    ) -> Bool;
+    fn XCompositeQueryVersion(
+        display: *mut _XDisplay,
+        major: *mut c_int,
+        minor: *mut c_int,
+    ) -> c_int;
+    fn XCompositeRedirectWindow(display: *mut _XDisplay, window: xlib::Window, update: c_int);
+    fn XCompositeNameWindowPixmap(display: *mut _XDisplay, window: xlib::Window) -> Pixmap;
+}
+
+/// Off-screen copies of windows through the Composite extension of libXcomposite.
+///
+/// A redirected window is drawn into a pixmap of its own before it ends up on screen, so
+/// the pixmap holds all of the window even where others cover it.
+pub struct XComposite {
+    _private: (),
+}
+impl XComposite {
+    /// Checks that the server of `display` supports Composite 0.2, the first version that
+    /// can name window pixmaps.
+    ///
+    /// # Safety
+    /// `display` has to be open.
+    pub unsafe fn load(display: *mut _XDisplay) -> eyre::Result<Self> {
+        let (mut event_base, mut error_base) = (0, 0);
+        if XCompositeQueryExtension(display, &mut event_base, &mut error_base) == xlib::False {
+            bail!("The X server doesn't support Composite");
+        }
+        let (mut major, mut minor) = (0, 0);
+        XCompositeQueryVersion(display, &mut major, &mut minor);
+        if (major, minor) < (0, 2) {
+            bail!("The X server only supports Composite {major}.{minor}, 0.2 is needed");
+        }
+        Ok(Self { _private: () })
+    }
+    /// Has `window` drawn off screen, until the connection closes.
+    ///
+    /// # Safety
+    /// `display` has to be the one Composite was loaded for.
+    pub unsafe fn redirect(&self, display: *mut _XDisplay, window: xlib::Window) {
+        XCompositeRedirectWindow(display, window, REDIRECT_AUTOMATIC);
+    }
+    /// A pixmap holding the current contents of the redirected `window`.
+    ///
+    /// The pixmap stays with the window's current storage, so it needs naming again after
+    /// the window is mapped or resized. It's up to the caller to free it.
+    ///
+    /// # Safety
+    /// `display` has to be the one Composite was loaded for, and `window` redirected and
+    /// mapped.
+    pub unsafe fn name_pixmap(&self, display: *mut _XDisplay, window: xlib::Window) -> Pixmap {
+        XCompositeNameWindowPixmap(display, window)
+    }
+}
@@ -0,0 +1,95 @@
+use std::ffi::{c_int, c_ulong};
+
+use eyre::bail;
+use x11::xlib::{self, _XDisplay, Bool, Drawable, Time, XRectangle};
+
+use crate::recog::Rect;
+
+/// A damage object of the DAMAGE extension.
+pub type Damage = xlib::XID;
+
+/// Level of damage reports that sends one event per rectangle that got damaged since the
+/// damage was last cleared.
+const REPORT_DELTA_RECTANGLES: c_int = 1;
+
+/// `XDamageNotifyEvent` of libXdamage.
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct NotifyEvent {
+    type_: c_int,
+    serial: c_ulong,
+    send_event: Bool,
+    display: *mut _XDisplay,
+    drawable: Drawable,
+    damage: Damage,
+    level: c_int,
+    more: Bool,
+    timestamp: Time,
+    area: XRectangle,
+    geometry: XRectangle,
+}
+
+// The x11 crate has no bindings of libXdamage
+#[link(name = "Xdamage")]
+extern "C" {
+    fn XDamageQueryExtension(
+        display: *mut _XDisplay,
+        event_base: *mut c_int,
+        error_base: *mut c_int,
+    ) -> Bool;
+    fn XDamageCreate(display: *mut _XDisplay, drawable: Drawable, level: c_int) -> Damage;
+    fn XDamageSubtract(
+        display: *mut _XDisplay,
+        damage: Damage,
+        repair: xlib::XID,
+        parts: xlib::XID,
+    );
+}
+
+/// Reports of which parts of a window changed through the DAMAGE extension of libXdamage.
+pub struct XDamage {
+    /// Type of damage notify events.
+    event_type: c_int,
+}
+impl XDamage {
+    /// Checks that the server of `display` supports DAMAGE.
+    ///
+    /// # Safety
+    /// `display` has to be open.
+    pub unsafe fn load(display: *mut _XDisplay) -> eyre::Result<Self> {
+        let (mut event_base, mut error_base) = (0, 0);
+        if XDamageQueryExtension(display, &mut event_base, &mut error_base) == xlib::False {
+            bail!("The X server doesn't support DAMAGE");
+        }
+        Ok(Self {
+            event_type: event_base,
+        })
+    }
+    /// Starts reporting damage of `window`. The damage goes away along with the window.
+    ///
+    /// # Safety
+    /// `display` has to be the one DAMAGE was loaded for.
+    pub unsafe fn watch(&self, display: *mut _XDisplay, window: xlib::Window) -> Damage {
+        XDamageCreate(display, window, REPORT_DELTA_RECTANGLES)
+    }
+    /// Forgets the damage so far, so the next change gets reported again.
+    ///
+    /// # Safety
+    /// `display` has to be the one DAMAGE was loaded for.
+    pub unsafe fn clear(&self, display: *mut _XDisplay, damage: Damage) {
+        XDamageSubtract(display, damage, 0, 0);
+    }
+    /// The damaged area of `damage` if `event` reports any.
+    pub fn damaged(&self, event: &xlib::XEvent, damage: Damage) -> Option<Rect> {
+        if event.get_type() != self.event_type {
+            return None;
+        }
+        // Safe since the event is of the damage notify type
+        let event = unsafe { &*(event as *const xlib::XEvent).cast::<NotifyEvent>() };
+        let area = event.area;
+        (event.damage == damage).then(|| {
+            let [x, y] = [area.x, area.y].map(|v| v.max(0) as u32);
+            Rect::new(x, y, area.width.into(), area.height.into())
+        })
+    }
+}
@@ -0,0 +1,164 @@
+use std::{
+    cell::{Cell, RefCell},
+    ffi::{c_char, c_int, c_void, CStr, CString},
+    fmt, ptr,
+    sync::{Once, OnceLock},
+};
+
+use eyre::{bail, ensure, WrapErr};
+use x11::xlib::{self, _XDisplay};
+
+/// An X protocol error, reported by the server some time after the request that caused it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct XError {
+    pub code: u8,
+    pub request: u8,
+    pub resource: xlib::XID,
+    message: String,
+}
+impl XError {
+    /// Whether the error means the window or something it's drawn into is gone, which
+    /// can happen at any moment.
+    pub fn is_gone(&self) -> bool {
+        self.code == xlib::BadWindow || self.code == xlib::BadDrawable
+    }
+}
+impl fmt::Display for XError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "X error {}: {} (request {}, resource {:#x})",
+            self.code, self.message, self.request, self.resource
+        )
+    }
+}
+impl std::error::Error for XError {}
+
+thread_local! {
+    /// The first error reported on this thread since the last check.
+    static ERROR: RefCell<Option<XError>> = const { RefCell::new(None) };
+}
+
+/// Records the error for the thread that made the request, instead of Xlib's default of
+/// printing it and exiting.
+unsafe extern "C" fn record(display: *mut _XDisplay, event: *mut xlib::XErrorEvent) -> c_int {
+    let event = &*event;
+    let mut text: [c_char; 256] = [0; 256];
+    xlib::XGetErrorText(
+        display,
+        event.error_code as c_int,
+        text.as_mut_ptr(),
+        text.len() as c_int,
+    );
+    let error = XError {
+        code: event.error_code,
+        request: event.request_code,
+        resource: event.resourceid,
+        message: CStr::from_ptr(text.as_ptr()).to_string_lossy().into_owned(),
+    };
+    let _ = ERROR.try_with(|first| {
+        if let Ok(mut first) = first.try_borrow_mut() {
+            first.get_or_insert(error);
+        }
+    });
+    0
+}
+
+thread_local! {
+    /// Whether the connection of a display used on this thread broke.
+    static LOST: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Records that the connection broke for the thread that used it, instead of Xlib's
+/// default of printing it.
+unsafe extern "C" fn lose(_display: *mut _XDisplay) -> c_int {
+    let _ = LOST.try_with(|lost| lost.set(true));
+    0
+}
+
+/// Returns to the request that found the connection broken, instead of Xlib's default of
+/// exiting the process. Every request on the display does nothing from then on.
+unsafe extern "C" fn stay(_display: *mut _XDisplay, _data: *mut c_void) {}
+
+/// `XSetIOErrorExitHandler`, which the x11 crate doesn't know about yet.
+type SetIOErrorExitHandler = unsafe extern "C" fn(
+    *mut _XDisplay,
+    Option<unsafe extern "C" fn(*mut _XDisplay, *mut c_void)>,
+    *mut c_void,
+);
+
+/// Looks up `XSetIOErrorExitHandler` in the loaded libX11, which has it since 1.7.
+/// Linking it directly would keep the crate from running with older versions.
+fn io_error_exit_handler() -> Option<SetIOErrorExitHandler> {
+    static FUNCTION: OnceLock<Option<SetIOErrorExitHandler>> = OnceLock::new();
+    *FUNCTION.get_or_init(|| {
+        let symbol = unsafe { libc::dlsym(libc::RTLD_DEFAULT, c"XSetIOErrorExitHandler".as_ptr()) };
+        // SAFETY: Every libX11 that has the function declares it with this signature
+        (!symbol.is_null())
+            .then(|| unsafe { std::mem::transmute::<*mut c_void, SetIOErrorExitHandler>(symbol) })
+    })
+}
+
+/// Opens the display called `name`, the default one without a name, with errors on it
+/// recorded rather than fatal.
+pub fn open_display(name: Option<&str>) -> eyre::Result<*mut _XDisplay> {
+    static INSTALL: Once = Once::new();
+    INSTALL.call_once(|| unsafe {
+        xlib::XSetErrorHandler(Some(record));
+        // Older versions of Xlib exit after any I/O error handler, so theirs is kept to
+        // say why
+        if io_error_exit_handler().is_some() {
+            xlib::XSetIOErrorHandler(Some(lose));
+        }
+    });
+    let c_name = name
+        .map(CString::new)
+        .transpose()
+        .wrap_err_with(|| format!("Invalid X display {name:?}"))?;
+    let display =
+        unsafe { xlib::XOpenDisplay(c_name.as_ref().map_or(ptr::null(), |n| n.as_ptr())) };
+    if display.is_null() {
+        let name = name.map_or_else(
+            || std::env::var("DISPLAY").unwrap_or_default(),
+            str::to_owned,
+        );
+        bail!("Failed to open the X display {name:?}");
+    }
+    if let Some(set_exit_handler) = io_error_exit_handler() {
+        unsafe { set_exit_handler(display, Some(stay), ptr::null_mut()) };
+    }
+    Ok(display)
+}
+
+/// Fails once the connection of a display used on this thread broke, which leaves the
+/// display good for nothing but closing it.
+pub fn connected() -> eyre::Result<()> {
+    ensure!(!LOST.with(Cell::get), "Lost the connection to the X server");
+    Ok(())
+}
+
+/// Takes the first error reported on this thread since the last call.
+pub fn take() -> Option<XError> {
+    ERROR.with(|first| first.borrow_mut().take())
+}
+
+/// Fails with the first error reported on this thread since the last call.
+///
+/// Only errors of requests the server already answered have been reported, so this is
+/// meant for after requests that wait for a reply.
+pub fn check() -> Result<(), XError> {
+    match take() {
+        Some(error) => Err(error),
+        None => Ok(()),
+    }
+}
+
+/// Waits for the server to process all requests sent on `display` so far, and fails with
+/// the first error any of them caused.
+///
+/// # Safety
+/// `display` has to be open and opened by [`open_display`].
+pub unsafe fn sync(display: *mut _XDisplay) -> Result<(), XError> {
+    xlib::XSync(display, xlib::False);
+    check()
+}
@@ -0,0 +1,167 @@
+use std::{
+    ffi::{c_int, c_uchar, c_ulong, CStr},
+    ptr, slice,
+};
+
+use x11::xlib::{self, _XDisplay};
+
+use super::error;
+use crate::window::{WindowInfo, WindowQuery};
+
+/// Finds the window described by `name` among all windows of the display called
+/// `display`, the default one without a name.
+///
+/// See [`WindowQuery`] for the ways to describe it.
+pub fn find_window(display: Option<&str>, name: &str) -> eyre::Result<xlib::Window> {
+    let query = WindowQuery::parse(name)?;
+    let display = error::open_display(display)?;
+    let windows = unsafe {
+        let windows = list_windows(display);
+        xlib::XCloseDisplay(display);
+        windows
+    };
+    // Windows vanishing while walking the tree is no reason to fail
+    error::take();
+    Ok(query.find(&windows)?.id as xlib::Window)
+}
+
+/// Every window below the root windows that has a title, class or pid.
+///
+/// The whole tree is walked rather than just the children of the root, since window managers
+/// reparent the clients into frames of their own.
+unsafe fn list_windows(display: *mut _XDisplay) -> Vec<WindowInfo> {
+    let atoms = Atoms::new(display);
+    let mut windows = Vec::new();
+    let mut stack: Vec<_> = (0..xlib::XScreenCount(display))
+        .map(|screen| xlib::XRootWindow(display, screen))
+        .collect();
+    while let Some(window) = stack.pop() {
+        stack.extend(children(display, window));
+        let info = atoms.describe(display, window);
+        if info.title.is_some() || !info.class.is_empty() || info.pid.is_some() {
+            windows.push(info);
+        }
+    }
+    windows
+}
+
+unsafe fn children(display: *mut _XDisplay, window: xlib::Window) -> Vec<xlib::Window> {
+    let (mut root, mut parent) = (0, 0);
+    let mut children = ptr::null_mut();
+    let mut count = 0;
+    let status = xlib::XQueryTree(
+        display,
+        window,
+        &mut root,
+        &mut parent,
+        &mut children,
+        &mut count,
+    );
+    if status == 0 || children.is_null() {
+        return Vec::new();
+    }
+    let list = slice::from_raw_parts(children, count as usize).to_vec();
+    xlib::XFree(children.cast());
+    list
+}
+
+/// The properties a window is recognised by.
+struct Atoms {
+    net_wm_name: xlib::Atom,
+    net_wm_pid: xlib::Atom,
+    utf8_string: xlib::Atom,
+}
+impl Atoms {
+    unsafe fn new(display: *mut _XDisplay) -> Self {
+        let atom = |name: &CStr| xlib::XInternAtom(display, name.as_ptr(), xlib::True);
+        Self {
+            net_wm_name: atom(c"_NET_WM_NAME"),
+            net_wm_pid: atom(c"_NET_WM_PID"),
+            utf8_string: atom(c"UTF8_STRING"),
+        }
+    }
+    unsafe fn describe(&self, display: *mut _XDisplay, window: xlib::Window) -> WindowInfo {
+        // _NET_WM_NAME is always UTF-8, WM_NAME usually Latin-1 but nobody checks
+        let title = property(display, window, self.net_wm_name, self.utf8_string)
+            .or_else(|| {
+                property(
+                    display,
+                    window,
+                    xlib::XA_WM_NAME,
+                    xlib::AnyPropertyType as _,
+                )
+            })
+            .map(|(_, bytes)| String::from_utf8_lossy(&bytes).into_owned());
+        let class = property(display, window, xlib::XA_WM_CLASS, xlib::XA_STRING)
+            .map(|(_, bytes)| {
+                bytes
+                    .split(|&b| b == 0)
+                    .filter(|name| !name.is_empty())
+                    .map(|name| String::from_utf8_lossy(name).into_owned())
+                    .collect()
+            })
+            .unwrap_or_default();
+        let pid = property(display, window, self.net_wm_pid, xlib::XA_CARDINAL).and_then(
+            |(format, bytes)| {
+                let item = bytes.get(..size_of::<c_ulong>()).filter(|_| format == 32)?;
+                Some(c_ulong::from_ne_bytes(item.try_into().ok()?) as u32)
+            },
+        );
+        let mut attributes: xlib::XWindowAttributes = std::mem::zeroed();
+        let viewable = xlib::XGetWindowAttributes(display, window, &mut attributes) != 0
+            && attributes.map_state == xlib::IsViewable;
+        WindowInfo {
+            id: window,
+            title,
+            class,
+            pid,
+            viewable,
+        }
+    }
+}
+
+/// The format and raw data of a property of `window`, if it has one of type `kind`.
+///
+/// Xlib hands out 32 bit items as C longs, so that's what their bytes hold.
+unsafe fn property(
+    display: *mut _XDisplay,
+    window: xlib::Window,
+    property: xlib::Atom,
+    kind: xlib::Atom,
+) -> Option<(c_int, Vec<u8>)> {
+    if property == 0 {
+        return None;
+    }
+    let mut actual_type = 0;
+    let mut format = 0;
+    let mut items = 0;
+    let mut remaining = 0;
+    let mut data: *mut c_uchar = ptr::null_mut();
+    let status = xlib::XGetWindowProperty(
+        display,
+        window,
+        property,
+        0,
+        // In 32 bit units, plenty for titles
+        4096,
+        xlib::False,
+        kind,
+        &mut actual_type,
+        &mut format,
+        &mut items,
+        &mut remaining,
+        &mut data,
+    );
+    if status != xlib::Success as c_int || data.is_null() {
+        return None;
+    }
+    let item_size = match format {
+        8 => 1,
+        16 => size_of::<std::ffi::c_short>(),
+        32 => size_of::<std::ffi::c_long>(),
+        _ => 0,
+    };
+    let bytes = slice::from_raw_parts(data, items as usize * item_size).to_vec();
+    xlib::XFree(data.cast());
+    (actual_type != 0).then_some((format, bytes))
+}
@@ -1,138 +1,427 @@
-use crate::control::{Controller, Eyes, GuiContext, ToBrain, ToController};
+use crate::{
+    control::{Controller, GuiContext, InputMethod, ToController, WindowParams},
+    follow::{self, Capture, Change, Geometry, WindowEyes, WindowSource},
+    pixels::{self, PixelFormat},
+};
 
-use eyre::Context;
-use image::{ImageBuffer, Rgb};
+use composite::XComposite;
+use damage::{Damage, XDamage};
+use error::XError;
+use eyre::ensure;
+use image::RgbImage;
+use shm::{SetupError, ShmCapture};
 use std::{
-    process::Command,
-    ptr,
-    sync::mpsc::{Receiver, SyncSender},
+    slice,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        mpsc::Receiver,
+        Arc,
+    },
+    time::Duration,
 };
 use x11::xlib::{self, _XDisplay};
+use xtest::XTest;
+
+mod composite;
+mod damage;
+mod error;
+mod lookup;
+mod shm;
+mod xtest;
+
+/// Keycode of the key that casts, the backtick on a standard keyboard.
+const CAST_KEYCODE: u32 = 49;
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone)]
 pub struct XContext {
-    window: xlib::Window,
+    /// The game window, replaced by the eyes when the game restarts.
+    window: Arc<AtomicU64>,
+    name: String,
+    params: WindowParams,
 }
 impl GuiContext for XContext {
     type Controller = XController;
     type Eyes = XEyes;
 
-    fn from_window_name(name: &str) -> eyre::Result<Self> {
-        let window = get_window_id_by_title(name)?;
-        Ok(Self { window })
+    fn from_window_name(name: &str, params: &WindowParams) -> eyre::Result<Self> {
+        let window = lookup::find_window(params.display.as_deref(), name)?;
+        Ok(Self {
+            window: Arc::new(AtomicU64::new(window)),
+            name: name.to_owned(),
+            params: params.clone(),
+        })
     }
 
     fn controller(&self) -> eyre::Result<Self::Controller> {
-        XController::new(self.window)
+        XController::new(self.window.clone(), &self.params)
     }
 
     fn eyes(&self) -> eyre::Result<Self::Eyes> {
-        XEyes::new(self.window)
+        let source = XSource::new(self.window.clone(), &self.params)?;
+        XEyes::new(source, self.name.clone(), &self.params)
     }
 }
 
 pub struct XController {
-    window: xlib::Window,
+    window: Arc<AtomicU64>,
     display: *mut _XDisplay,
+    /// Faked device input, when asked for and supported. Otherwise events are sent to the
+    /// window.
+    xtest: Option<XTest>,
 }
 
-pub struct XEyes {
-    window: xlib::Window,
-    display: *mut _XDisplay,
-}
+/// The eyes, following the game window through Xlib.
+pub type XEyes = WindowEyes<XSource>;
 
-fn get_window_id_by_title(name: &str) -> eyre::Result<xlib::Window> {
-    let output = Command::new("xdotool")
-        .args(&["search", "--name", &format!("{name}$")])
-        .output()?;
-
-    let binding =
-        String::from_utf8(output.stdout).expect("Error converting process stdout to utf8");
-    let window_id_str = binding.as_str();
-    let window_id = window_id_str
-        .trim()
-        .parse()
-        .context(format!("Window {name} not found"))?;
-    Ok(window_id)
+pub struct XSource {
+    window: Arc<AtomicU64>,
+    /// Name of the display, to look for the window on it again.
+    display_name: Option<String>,
+    display: *mut _XDisplay,
+    /// Geometry and visual of the window, as of the last refresh.
+    attributes: xlib::XWindowAttributes,
+    /// Shared memory capture, set up for the current size of the window.
+    shm: Option<ShmCapture>,
+    /// Whether shared memory capture can't ever work, leaving only `XGetImage`.
+    shm_failed: bool,
+    /// Off-screen copies of the window, when capturing it even where it's covered.
+    composite: Option<XComposite>,
+    /// The off-screen copy of the window, once named for its current storage.
+    pixmap: Option<xlib::Pixmap>,
+    /// Damage reports of the window, when capturing only after changes.
+    damage: Option<(XDamage, Damage)>,
 }
 
-impl XEyes {
-    pub fn new(window: xlib::Window) -> eyre::Result<Self> {
+impl XSource {
+    pub fn new(window: Arc<AtomicU64>, params: &WindowParams) -> eyre::Result<Self> {
+        let display = error::open_display(params.display.as_deref())?;
+        let damage = match params.damage {
+            true => match unsafe { XDamage::load(display) } {
+                Ok(damage) => Some((damage, 0)),
+                Err(e) => {
+                    println!("Warning: {e:#}, capturing continuously");
+                    None
+                }
+            },
+            false => None,
+        };
+        let composite = match params.composite {
+            true => match unsafe { XComposite::load(display) } {
+                Ok(composite) => Some(composite),
+                Err(e) => {
+                    println!("Warning: {e:#}, capturing only the visible parts of the window");
+                    None
+                }
+            },
+            false => None,
+        };
         Ok(Self {
             window,
-            display: unsafe { xlib::XOpenDisplay(ptr::null()) },
+            display_name: params.display.clone(),
+            display,
+            attributes: unsafe { std::mem::zeroed() },
+            shm: None,
+            shm_failed: false,
+            composite,
+            pixmap: None,
+            damage,
         })
     }
 
-    pub fn get_image(&self) -> eyre::Result<ImageBuffer<Rgb<u8>, Vec<u8>>> {
-        unsafe {
-            // Get the window attributes
-            let mut window_attributes: xlib::XWindowAttributes = std::mem::zeroed();
-            xlib::XGetWindowAttributes(self.display, self.window, &mut window_attributes);
+    /// What to capture from, the off-screen copy of the window if there's one.
+    unsafe fn drawable(&mut self) -> xlib::Drawable {
+        let window = self.window();
+        let Some(composite) = &self.composite else {
+            return window;
+        };
+        if let Some(pixmap) = self.pixmap {
+            return pixmap;
+        }
+        let pixmap = composite.name_pixmap(self.display, window);
+        match error::sync(self.display) {
+            Ok(()) => {
+                self.pixmap = Some(pixmap);
+                pixmap
+            }
+            Err(e) if e.is_gone() => window,
+            Err(e) => {
+                println!("Warning: {e}, capturing only the visible parts of the window");
+                self.composite = None;
+                window
+            }
+        }
+    }
 
-            // Get the dimensions of the window
-            let width = window_attributes.width as u32;
-            let height = window_attributes.height as u32;
+    /// Sets up shared memory capture for `size`, unless it already is or can't work.
+    unsafe fn prepare_shm(&mut self, size: [u32; 2]) {
+        if self.shm_failed
+            || size.contains(&0)
+            || self.shm.as_ref().is_some_and(|shm| shm.size() == size)
+        {
+            return;
+        }
+        if let Some(shm) = self.shm.take() {
+            shm.destroy(self.display);
+        }
+        let shm = ShmCapture::new(
+            self.display,
+            self.attributes.visual,
+            self.attributes.depth,
+            size[0],
+            size[1],
+        );
+        match shm {
+            Ok(shm) => self.shm = Some(shm),
+            Err(e @ SetupError::Unsupported(_)) => {
+                println!("Warning: {e}, capturing without shared memory");
+                self.shm_failed = true;
+            }
+            // Tried again once the window has another size
+            Err(e @ SetupError::Failed(_)) => {
+                println!("Warning: {e}, capturing this size without shared memory");
+            }
+        }
+    }
 
-            // Create an XImage structure to hold the screenshot
-            let image = xlib::XGetImage(
-                self.display,
-                self.window,
-                0,
-                0,
-                width,
-                height,
-                xlib::XAllPlanes(),
-                xlib::ZPixmap,
-            );
+    /// Captures the window, which is `size` big.
+    unsafe fn grab(&mut self, size: [u32; 2]) -> eyre::Result<RgbImage> {
+        let window = self.drawable();
+        let visual = self.attributes.visual;
+
+        self.prepare_shm(size);
+        if let Some(shm) = &mut self.shm {
+            let image = shm.capture(self.display, window)?;
+            return to_rgb(image, visual);
+        }
+
+        // Create an XImage structure to hold the screenshot
+        let [width, height] = size;
+        let image = xlib::XGetImage(
+            self.display,
+            window,
+            0,
+            0,
+            width,
+            height,
+            xlib::XAllPlanes(),
+            xlib::ZPixmap,
+        );
+        error::check()?;
+        ensure!(!image.is_null(), "Failed to capture the window");
+        let image_buffer = to_rgb(image, visual);
+
+        // Clean up
+        xlib::XDestroyImage(image);
+        image_buffer
+    }
+}
+impl WindowSource for XSource {
+    type Window = xlib::Window;
+
+    fn window(&self) -> xlib::Window {
+        self.window.load(Ordering::Relaxed)
+    }
+
+    fn find(&mut self, name: &str) -> eyre::Result<xlib::Window> {
+        lookup::find_window(self.display_name.as_deref(), name)
+    }
+
+    fn follow(&mut self, window: xlib::Window) -> eyre::Result<Geometry> {
+        self.window.store(window, Ordering::Relaxed);
+        unsafe {
+            xlib::XSelectInput(self.display, window, xlib::StructureNotifyMask);
+            if let Some(composite) = &self.composite {
+                composite.redirect(self.display, window);
+            }
+            self.forget_pixmap()?;
+            if let Some((damage, id)) = &mut self.damage {
+                *id = damage.watch(self.display, window);
+            }
+        }
+        self.refresh()
+    }
 
-            // Process the image data
-            let mut buffer: Vec<u8> = Vec::with_capacity((width * height * 4) as usize);
+    fn refresh(&mut self) -> eyre::Result<Geometry> {
+        let status = unsafe {
+            xlib::XGetWindowAttributes(self.display, self.window(), &mut self.attributes)
+        };
+        error::connected()?;
+        error::check()?;
+        let attributes = &self.attributes;
+        Ok(Geometry {
+            size: [attributes.width as u32, attributes.height as u32],
+            viewable: status != 0 && attributes.map_state == xlib::IsViewable,
+        })
+    }
 
-            for y in 0..height {
-                for x in 0..width {
-                    let pixel = xlib::XGetPixel(image, x as i32, y as i32);
-                    buffer.push(((pixel >> 16) & 0xFF) as u8); // Red
-                    buffer.push(((pixel >> 8) & 0xFF) as u8); // Green
-                    buffer.push((pixel & 0xFF) as u8); // Blue
+    fn next_change(&mut self) -> eyre::Result<Option<Change>> {
+        let window = self.window();
+        // Xlib has no events to tell once the connection broke
+        error::connected()?;
+        unsafe {
+            while xlib::XPending(self.display) > 0 {
+                let mut event: xlib::XEvent = std::mem::zeroed();
+                xlib::XNextEvent(self.display, &mut event);
+                if let Some((damage, id)) = &self.damage {
+                    if let Some(area) = damage.damaged(&event, *id) {
+                        return Ok(Some(Change::Damaged(area)));
+                    }
                 }
+                let change = match event.get_type() {
+                    xlib::UnmapNotify if event.unmap.window == window => Change::Unmapped,
+                    xlib::MapNotify if event.map.window == window => Change::Mapped,
+                    xlib::ConfigureNotify if event.configure.window == window => {
+                        let configure = event.configure;
+                        Change::Configured([configure.width as u32, configure.height as u32])
+                    }
+                    xlib::DestroyNotify if event.destroy_window.window == window => {
+                        Change::Destroyed
+                    }
+                    _ => continue,
+                };
+                return Ok(Some(change));
             }
+        }
+        Ok(None)
+    }
 
-            // Create an ImageBuffer from the pixel data
-            let image_buffer = ImageBuffer::<Rgb<u8>, _>::from_raw(width, height, buffer).unwrap();
+    fn wait(&mut self, timeout: Option<Duration>) -> eyre::Result<bool> {
+        // Xlib may have read events off the connection already
+        if unsafe { xlib::XPending(self.display) } > 0 {
+            return Ok(true);
+        }
+        error::connected()?;
+        let fd = unsafe { xlib::XConnectionNumber(self.display) };
+        Ok(follow::wait_readable(fd, timeout)?)
+    }
+
+    fn reports_damage(&self) -> bool {
+        self.damage.is_some()
+    }
 
-            // Clean up
-            xlib::XDestroyImage(image);
-            return Ok(image_buffer);
+    fn clear_damage(&mut self) -> eyre::Result<()> {
+        if let Some((damage, id)) = &self.damage {
+            unsafe { damage.clear(self.display, *id) };
         }
+        Ok(())
     }
+
+    fn forget_pixmap(&mut self) -> eyre::Result<()> {
+        if let Some(pixmap) = self.pixmap.take() {
+            unsafe { xlib::XFreePixmap(self.display, pixmap) };
+        }
+        Ok(())
+    }
+
+    fn capture(&mut self, size: [u32; 2]) -> eyre::Result<Capture> {
+        match unsafe { self.grab(size) } {
+            Ok(frame) => Ok(Capture::Frame(frame)),
+            // Errors of the server only concern this frame, unless the connection broke
+            Err(e) if e.is::<XError>() => {
+                error::connected()?;
+                Ok(Capture::Refused(e))
+            }
+            Err(e) => {
+                error::connected()?;
+                Err(e)
+            }
+        }
+    }
+}
+impl Drop for XSource {
+    fn drop(&mut self) {
+        unsafe {
+            if let Some(shm) = self.shm.take() {
+                shm.destroy(self.display);
+            }
+            let _ = self.forget_pixmap();
+            // Closing the connection also ends the redirection of the window
+            xlib::XCloseDisplay(self.display);
+        }
+    }
+}
+
+/// Copies the pixels of `image` into an RGB image, reading them as described by the image
+/// and the window's `visual`.
+unsafe fn to_rgb(image: *mut xlib::XImage, visual: *const xlib::Visual) -> eyre::Result<RgbImage> {
+    let image = &*image;
+    // Images usually carry the masks of the visual, but nothing requires them to
+    let masks = match (
+        [image.red_mask, image.green_mask, image.blue_mask],
+        visual.as_ref(),
+    ) {
+        ([0, 0, 0], Some(visual)) => [visual.red_mask, visual.green_mask, visual.blue_mask],
+        (masks, _) => masks,
+    };
+    let [red_mask, green_mask, blue_mask] = masks.map(|mask| mask as u32);
+    let format = PixelFormat {
+        bits_per_pixel: image.bits_per_pixel as u32,
+        bytes_per_line: image.bytes_per_line as usize,
+        red_mask,
+        green_mask,
+        blue_mask,
+        big_endian: image.byte_order == xlib::MSBFirst,
+    };
+    let (width, height) = (image.width as u32, image.height as u32);
+    let data = slice::from_raw_parts(
+        image.data as *const u8,
+        format.bytes_per_line * height as usize,
+    );
+    pixels::to_rgb(&format, width, height, data)
 }
 
 impl XController {
-    pub fn new(window: xlib::Window) -> eyre::Result<Self> {
+    pub fn new(window: Arc<AtomicU64>, params: &WindowParams) -> eyre::Result<Self> {
+        let display = error::open_display(params.display.as_deref())?;
+        let xtest = match params.input {
+            InputMethod::SendEvent => None,
+            InputMethod::XTest => match unsafe { XTest::load(display) } {
+                Ok(xtest) => Some(xtest),
+                Err(e) => {
+                    println!("Warning: {e:#}, sending events to the window instead");
+                    None
+                }
+            },
+        };
         Ok(Self {
             window,
-            display: unsafe { xlib::XOpenDisplay(ptr::null()) },
+            display,
+            xtest,
         })
     }
 
+    /// The game window, which changes when the game restarts.
+    fn window(&self) -> xlib::Window {
+        self.window.load(Ordering::Relaxed)
+    }
+
     pub fn move_mouse_to_coordinate(&self, x: i32, y: i32) {
         unsafe {
+            if let Some(xtest) = &self.xtest {
+                xtest.move_to(self.display, self.window(), [x, y]);
+                return;
+            }
             // Move the mouse to the specified coordinates
-            xlib::XWarpPointer(self.display, 0, self.window, 0, 0, 0, 0, x, y);
+            xlib::XWarpPointer(self.display, 0, self.window(), 0, 0, 0, 0, x, y);
         }
     }
     pub fn left_click(&self, x: i32, y: i32) {
         unsafe {
+            if let Some(xtest) = &self.xtest {
+                xtest.move_to(self.display, self.window(), [x, y]);
+                xtest.button(self.display, 1, true);
+                xtest.button(self.display, 1, false);
+                return;
+            }
             // Create a button press event
             let button_event: xlib::XButtonEvent = xlib::XButtonEvent {
                 type_: xlib::ButtonPress,
                 display: self.display,
-                window: self.window,
+                window: self.window(),
                 subwindow: 0,
                 time: 0,
-                x: x,
-                y: y,
+                x,
+                y,
                 same_screen: xlib::True,
                 button: 1,
                 ..std::mem::zeroed()
@@ -145,7 +434,7 @@ impl XController {
             // Send the button press event
             xlib::XSendEvent(
                 self.display,
-                self.window,
+                self.window(),
                 xlib::True,
                 xlib::ButtonPressMask,
                 &mut xevent,
@@ -154,11 +443,11 @@ impl XController {
             let button_event: xlib::XButtonEvent = xlib::XButtonEvent {
                 type_: xlib::ButtonRelease,
                 display: self.display,
-                window: self.window,
+                window: self.window(),
                 subwindow: 0,
                 time: 0,
-                x: x,
-                y: y,
+                x,
+                y,
                 same_screen: xlib::True,
                 button: 1,
                 ..std::mem::zeroed()
@@ -171,7 +460,7 @@ impl XController {
             // Send the button press event
             xlib::XSendEvent(
                 self.display,
-                self.window,
+                self.window(),
                 xlib::True,
                 xlib::ButtonReleaseMask,
                 &mut xevent,
@@ -180,16 +469,21 @@ impl XController {
     }
     pub fn cast_hook(&self) {
         unsafe {
+            if let Some(xtest) = &self.xtest {
+                xtest.key(self.display, CAST_KEYCODE, true);
+                xtest.key(self.display, CAST_KEYCODE, false);
+                return;
+            }
             let key_event: xlib::XKeyEvent = xlib::XKeyEvent {
                 type_: xlib::KeyPress,
                 display: self.display,
-                window: self.window,
+                window: self.window(),
                 subwindow: 0,
                 time: 0,
                 x: 0,
                 y: 0,
                 same_screen: xlib::True,
-                keycode: 49,
+                keycode: CAST_KEYCODE,
                 ..std::mem::zeroed()
             };
 
@@ -197,7 +491,7 @@ impl XController {
 
             xlib::XSendEvent(
                 self.display,
-                self.window,
+                self.window(),
                 xlib::True,
                 xlib::KeyPressMask,
                 &mut xevent,
@@ -206,13 +500,13 @@ impl XController {
             let key_event: xlib::XKeyEvent = xlib::XKeyEvent {
                 type_: xlib::KeyRelease,
                 display: self.display,
-                window: self.window,
+                window: self.window(),
                 subwindow: 0,
                 time: 0,
                 x: 0,
                 y: 0,
                 same_screen: xlib::True,
-                keycode: 49,
+                keycode: CAST_KEYCODE,
                 ..std::mem::zeroed()
             };
 
@@ -220,7 +514,7 @@ impl XController {
 
             xlib::XSendEvent(
                 self.display,
-                self.window,
+                self.window(),
                 xlib::True,
                 xlib::KeyReleaseMask,
                 &mut xevent,
@@ -236,22 +530,26 @@ impl Controller for XController {
                 ToController::PerformClick([x, y]) => self.left_click(x, y),
                 ToController::CastHook => self.cast_hook(),
             }
-            unsafe {
-                xlib::XFlush(self.display);
+            let synced = unsafe { error::sync(self.display) };
+            error::connected()?;
+            match synced {
+                Ok(()) => {}
+                // The eyes notice and wait for the game to come back
+                Err(e) if e.is_gone() => println!("Warning: {e}, the game window is gone"),
+                Err(e) => return Err(e.into()),
             }
         }
     }
 }
-impl Eyes for XEyes {
-    fn run(self, send: SyncSender<ToBrain>) -> eyre::Result<()> {
-        loop {
-            send.send(ToBrain::NextFrame(self.get_image()?))?;
+impl Drop for XController {
+    fn drop(&mut self) {
+        unsafe {
+            xlib::XCloseDisplay(self.display);
         }
     }
 }
-
 unsafe impl Send for XController {}
 unsafe impl Sync for XController {}
 
-unsafe impl Send for XEyes {}
-unsafe impl Sync for XEyes {}
+unsafe impl Send for XSource {}
+unsafe impl Sync for XSource {}
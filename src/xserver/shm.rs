@@ -0,0 +1,145 @@
+use std::{
+    ffi::{c_int, c_uint},
+    fmt, ptr,
+};
+
+use eyre::bail;
+use x11::{
+    xlib::{self, _XDisplay, Visual, XImage},
+    xshm::{
+        XShmAttach, XShmCreateImage, XShmDetach, XShmGetImage, XShmQueryExtension, XShmSegmentInfo,
+    },
+};
+
+use super::error;
+
+// The x11 crate declares the MIT-SHM functions without linking the library they live in
+#[link(name = "Xext")]
+extern "C" {}
+
+/// Why shared memory capture couldn't be set up.
+#[derive(Debug)]
+pub enum SetupError {
+    /// The server can't share memory with this process at all, e.g. because it's remote.
+    Unsupported(eyre::Report),
+    /// Setting up failed for the current size of the window, it may work for another one.
+    Failed(eyre::Report),
+}
+impl fmt::Display for SetupError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SetupError::Unsupported(e) | SetupError::Failed(e) => write!(f, "{e:#}"),
+        }
+    }
+}
+
+/// Captures a window through a shared memory segment with the X server.
+///
+/// The server writes the window contents straight into memory this process can read, which
+/// saves copying every frame through the socket. The segment is kept for as long as the
+/// window keeps its size.
+pub struct ShmCapture {
+    info: XShmSegmentInfo,
+    image: *mut XImage,
+}
+impl ShmCapture {
+    /// Sets up capture of `width` by `height` px of windows with the given visual and depth.
+    ///
+    /// Fails for good when the extension is missing or the server can't attach the segment,
+    /// which is always the case over the network.
+    ///
+    /// # Safety
+    /// `display` has to be opened by [`error::open_display`] and stay open for as long as
+    /// the capture exists.
+    pub unsafe fn new(
+        display: *mut _XDisplay,
+        visual: *mut Visual,
+        depth: c_int,
+        width: u32,
+        height: u32,
+    ) -> Result<Self, SetupError> {
+        let failed = |message: String| SetupError::Failed(eyre::eyre!(message));
+        if XShmQueryExtension(display) == xlib::False {
+            return Err(SetupError::Unsupported(eyre::eyre!(
+                "The X server doesn't support MIT-SHM"
+            )));
+        }
+        let mut info: XShmSegmentInfo = std::mem::zeroed();
+        let image = XShmCreateImage(
+            display,
+            visual,
+            depth as c_uint,
+            xlib::ZPixmap,
+            ptr::null_mut(),
+            &mut info,
+            width,
+            height,
+        );
+        if image.is_null() {
+            return Err(failed(format!(
+                "Failed to create a {width}x{height} shared memory image"
+            )));
+        }
+        let size = (*image).bytes_per_line as usize * (*image).height as usize;
+        info.shmid = libc::shmget(libc::IPC_PRIVATE, size, libc::IPC_CREAT | 0o600);
+        if info.shmid < 0 {
+            xlib::XDestroyImage(image);
+            return Err(failed(format!(
+                "Failed to allocate {size} bytes of shared memory"
+            )));
+        }
+        info.shmaddr = libc::shmat(info.shmid, ptr::null(), 0).cast();
+        // Mark the segment for removal right away, it goes once both sides let go of it
+        libc::shmctl(info.shmid, libc::IPC_RMID, ptr::null_mut());
+        if info.shmaddr as isize == -1 {
+            xlib::XDestroyImage(image);
+            return Err(failed("Failed to attach shared memory".into()));
+        }
+        (*image).data = info.shmaddr;
+        info.readOnly = xlib::False;
+
+        // Attaching fails asynchronously with an X error, e.g. on remote displays
+        let attached = XShmAttach(display, &mut info) != xlib::False;
+        if !attached || error::sync(display).is_err() {
+            xlib::XDestroyImage(image);
+            libc::shmdt(info.shmaddr.cast());
+            return Err(SetupError::Unsupported(eyre::eyre!(
+                "The X server can't attach shared memory, it's probably remote"
+            )));
+        }
+        Ok(Self { info, image })
+    }
+    /// Width and height of the captured area, in px.
+    pub fn size(&self) -> [u32; 2] {
+        unsafe { [(*self.image).width as u32, (*self.image).height as u32] }
+    }
+    /// Captures the top left of `window` into the shared image and returns it.
+    ///
+    /// # Safety
+    /// `display` has to be the one the capture was set up with.
+    pub unsafe fn capture(
+        &mut self,
+        display: *mut _XDisplay,
+        window: xlib::Window,
+    ) -> eyre::Result<*mut XImage> {
+        // The binding takes the plane mask as 32 bits, which covers every depth
+        if XShmGetImage(display, window, self.image, 0, 0, c_uint::MAX) == 0 {
+            // The server said why, unless the connection broke
+            error::check()?;
+            bail!("Failed to capture the window through shared memory");
+        }
+        error::check()?;
+        Ok(self.image)
+    }
+    /// Releases the segment on the server and here.
+    ///
+    /// # Safety
+    /// `display` has to be the one the capture was set up with.
+    pub unsafe fn destroy(mut self, display: *mut _XDisplay) {
+        XShmDetach(display, &mut self.info);
+        xlib::XSync(display, xlib::False);
+        // Destroying a shared memory image leaves the data alone
+        xlib::XDestroyImage(self.image);
+        libc::shmdt(self.info.shmaddr.cast());
+    }
+}
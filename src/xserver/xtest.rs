@@ -0,0 +1,74 @@
+use std::ffi::c_uint;
+
+use eyre::bail;
+use x11::{
+    xlib::{self, _XDisplay},
+    xtest::{XTestFakeButtonEvent, XTestFakeKeyEvent, XTestFakeMotionEvent, XTestQueryExtension},
+};
+
+/// Faked device input through the XTEST extension of libXtst.
+///
+/// Unlike `XSendEvent`, the server can't tell these events from real input, so games that
+/// ignore synthetic events still take them. They go wherever real input would, so the game
+/// window has to be focused and on top.
+pub struct XTest {
+    _private: (),
+}
+impl XTest {
+    /// Checks that the server of `display` supports XTEST.
+    ///
+    /// # Safety
+    /// `display` has to be open.
+    pub unsafe fn load(display: *mut _XDisplay) -> eyre::Result<Self> {
+        let (mut event_base, mut error_base, mut major, mut minor) = (0, 0, 0, 0);
+        if XTestQueryExtension(
+            display,
+            &mut event_base,
+            &mut error_base,
+            &mut major,
+            &mut minor,
+        ) == 0
+        {
+            bail!("The X server doesn't support XTEST");
+        }
+        Ok(Self { _private: () })
+    }
+    /// Moves the pointer to `x`, `y` relative to `window`.
+    ///
+    /// # Safety
+    /// `display` has to be the one XTEST was loaded for.
+    pub unsafe fn move_to(&self, display: *mut _XDisplay, window: xlib::Window, [x, y]: [i32; 2]) {
+        let mut attributes: xlib::XWindowAttributes = std::mem::zeroed();
+        if xlib::XGetWindowAttributes(display, window, &mut attributes) == 0 {
+            // The window is gone, which the error handler has recorded
+            return;
+        }
+        let (mut root_x, mut root_y, mut child) = (0, 0, 0);
+        xlib::XTranslateCoordinates(
+            display,
+            window,
+            attributes.root,
+            x,
+            y,
+            &mut root_x,
+            &mut root_y,
+            &mut child,
+        );
+        let screen = xlib::XScreenNumberOfScreen(attributes.screen);
+        XTestFakeMotionEvent(display, screen, root_x, root_y, 0);
+    }
+    /// Presses or releases mouse `button`.
+    ///
+    /// # Safety
+    /// `display` has to be the one XTEST was loaded for.
+    pub unsafe fn button(&self, display: *mut _XDisplay, button: c_uint, pressed: bool) {
+        XTestFakeButtonEvent(display, button, pressed.into(), 0);
+    }
+    /// Presses or releases the key with `keycode`.
+    ///
+    /// # Safety
+    /// `display` has to be the one XTEST was loaded for.
+    pub unsafe fn key(&self, display: *mut _XDisplay, keycode: c_uint, pressed: bool) {
+        XTestFakeKeyEvent(display, keycode, pressed.into(), 0);
+    }
+}
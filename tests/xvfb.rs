@@ -0,0 +1,242 @@
+//! End-to-end tests of the X11 backend, the brain and the controller against a headless X
+//! server.
+//!
+//! A test window plays a synthetic cast once the cast key reaches it, while everything else
+//! runs as it would against the game. The tests need `Xvfb` on the `PATH`, so they only run
+//! when asked for, against the backend the crate is built with:
+//!
+//! ```text
+//! cargo test --test xvfb -- --ignored
+//! cargo test --test xvfb --no-default-features --features xcb -- --ignored
+//! ```
+#![cfg(any(feature = "xserver", feature = "xcb"))]
+
+use std::{
+    io::{BufRead, BufReader},
+    process::{Child, Command, Stdio},
+    thread,
+    time::{Duration, Instant},
+};
+
+use fischer::{
+    launch,
+    profile::Profile,
+    recog::{Brain, ColorDetector, ColorLut, Rect, SceneGenerator, SceneParams},
+};
+use x11rb::{
+    connection::Connection,
+    protocol::{
+        xproto::{
+            AtomEnum, ConnectionExt as _, CreateGCAux, CreateWindowAux, EventMask, Gcontext,
+            ImageFormat, PropMode, Window, WindowClass,
+        },
+        Event,
+    },
+    rust_connection::RustConnection,
+    wrapper::ConnectionExt as _,
+    COPY_DEPTH_FROM_PARENT,
+};
+
+/// Title of the test window, which the backend finds it by.
+const TITLE: &str = "fischer integration test";
+/// Keycode of the key that casts, the backtick on a standard keyboard.
+const CAST_KEYCODE: u8 = 49;
+/// How long a whole cast may take, from starting up to the click.
+const TIMEOUT: Duration = Duration::from_secs(60);
+/// Rows of the frame sent per request, which keeps requests below the size limit.
+const STRIP_ROWS: u32 = 64;
+
+/// A headless X server, stopped once dropped.
+struct Xvfb {
+    child: Child,
+    display: String,
+}
+impl Xvfb {
+    /// Starts Xvfb on a free display.
+    fn start() -> Self {
+        let mut child = Command::new("Xvfb")
+            .args(["-displayfd", "1", "-nolisten", "tcp"])
+            .args(["-screen", "0", "1024x768x24"])
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .unwrap_or_else(|e| panic!("Failed to start Xvfb, is it installed? {e}"));
+        // The server writes the number of the display it picked once it's ready
+        let mut number = String::new();
+        let stdout = child.stdout.take().expect("stdout is piped");
+        BufReader::new(stdout)
+            .read_line(&mut number)
+            .expect("Failed to read the display of Xvfb");
+        assert!(!number.trim().is_empty(), "Xvfb exited without a display");
+        Self {
+            child,
+            display: format!(":{}", number.trim()),
+        }
+    }
+}
+impl Drop for Xvfb {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// The stand-in for the game, a window showing a synthetic cast that starts when the cast
+/// key arrives.
+struct Pond {
+    conn: RustConnection,
+    window: Window,
+    gc: Gcontext,
+    scene: SceneGenerator,
+    /// When the cast key arrived.
+    cast: Option<Instant>,
+    /// Index of the frame on screen.
+    frame: Option<usize>,
+    /// Where the bobber is on screen.
+    bobber: Option<Rect>,
+}
+impl Pond {
+    fn open(display: &str, scene: SceneGenerator, [width, height]: [u16; 2]) -> Self {
+        let (conn, screen) = x11rb::connect(Some(display)).expect("Failed to connect to Xvfb");
+        let root = conn.setup().roots[screen].root;
+        let window = conn.generate_id().unwrap();
+        let events = EventMask::KEY_PRESS | EventMask::BUTTON_PRESS;
+        conn.create_window(
+            COPY_DEPTH_FROM_PARENT,
+            window,
+            root,
+            0,
+            0,
+            width,
+            height,
+            0,
+            WindowClass::INPUT_OUTPUT,
+            0,
+            &CreateWindowAux::new().event_mask(events),
+        )
+        .unwrap();
+        conn.change_property8(
+            PropMode::REPLACE,
+            window,
+            AtomEnum::WM_NAME,
+            AtomEnum::STRING,
+            TITLE.as_bytes(),
+        )
+        .unwrap();
+        let gc = conn.generate_id().unwrap();
+        conn.create_gc(gc, window, &CreateGCAux::new()).unwrap();
+        conn.map_window(window).unwrap();
+        let mut pond = Self {
+            conn,
+            window,
+            gc,
+            scene,
+            cast: None,
+            frame: None,
+            bobber: None,
+        };
+        pond.draw();
+        // Make sure the window is there before anyone looks for it
+        pond.conn.get_input_focus().unwrap().reply().unwrap();
+        pond
+    }
+    /// Shows the frame of the cast that's due, just the water before the cast.
+    fn draw(&mut self) {
+        let fps = SceneParams::default().fps;
+        let index = self
+            .cast
+            .map_or(0, |at| (at.elapsed().as_secs_f64() * fps) as usize)
+            .min(self.scene.frame_count() - 1);
+        if self.frame == Some(index) {
+            return;
+        }
+        let (frame, bobber) = self.scene.render(index);
+        // Xvfb stores depth 24 in 32 bits per pixel, least significant byte first
+        let data: Vec<u8> = frame.pixels().flat_map(|p| [p[2], p[1], p[0], 0]).collect();
+        let row = frame.width() as usize * 4;
+        for (i, strip) in data.chunks(row * STRIP_ROWS as usize).enumerate() {
+            let rows = (strip.len() / row) as u16;
+            let y = (i as u32 * STRIP_ROWS) as i16;
+            let width = frame.width() as u16;
+            self.conn
+                .put_image(
+                    ImageFormat::Z_PIXMAP,
+                    self.window,
+                    self.gc,
+                    width,
+                    rows,
+                    0,
+                    y,
+                    0,
+                    24,
+                    strip,
+                )
+                .unwrap();
+        }
+        self.conn.flush().unwrap();
+        self.frame = Some(index);
+        self.bobber = bobber;
+    }
+}
+
+#[test]
+#[ignore = "needs Xvfb, run with --ignored"]
+fn casts_and_clicks_the_bobber() {
+    let xvfb = Xvfb::start();
+
+    // A fish that surely bites, well after the bobber settled
+    let params = SceneParams {
+        duration: 12.0,
+        bite: (4.5, 5.5),
+        bite_chance: 1.0,
+        ..SceneParams::default()
+    };
+    let size = [params.width as u16, params.height as u16];
+    let mut pond = Pond::open(&xvfb.display, SceneGenerator::new(params, 0), size);
+
+    let mut profile = Profile::default();
+    profile.window.display = Some(xvfb.display.clone());
+    let detector = ColorDetector::new(ColorLut::new(&profile.thresholds), profile.scale.bobber);
+    let brain = Brain::new(&profile, Box::new(detector)).unwrap();
+    let handles = launch(TITLE, &profile.window, brain, None).expect("Failed to launch");
+
+    let deadline = Instant::now() + TIMEOUT;
+    let click = loop {
+        assert!(Instant::now() < deadline, "No click within {TIMEOUT:?}");
+        for (name, handle) in [
+            ("brain", &handles.brain),
+            ("eyes", &handles.eyes),
+            ("controller", &handles.controller),
+        ] {
+            assert!(!handle.is_finished(), "The {name} stopped");
+        }
+        match pond.conn.poll_for_event().unwrap() {
+            Some(Event::KeyPress(event)) if event.detail == CAST_KEYCODE => {
+                pond.cast.get_or_insert_with(Instant::now);
+            }
+            Some(Event::ButtonPress(event)) if event.detail == 1 => {
+                break [event.event_x, event.event_y];
+            }
+            Some(_) => {}
+            None => {
+                pond.draw();
+                thread::sleep(Duration::from_millis(10));
+            }
+        }
+    };
+
+    let cast = pond.cast.expect("Clicked without casting first");
+    let bite = pond.scene.bite_frame().expect("The fish bites") as f64 / SceneParams::default().fps;
+    assert!(
+        cast.elapsed().as_secs_f64() >= bite,
+        "Clicked {:?} after the cast, before the bite at {bite} s",
+        cast.elapsed()
+    );
+    let bobber = pond.bobber.expect("The bobber is on screen");
+    // Frames are in flight while the bobber moves, allow for a few of them
+    let margin = bobber.height as f64 / 2.0;
+    assert!(
+        bobber.contains(click.map(f64::from), margin),
+        "Clicked at {click:?}, the bobber is at {bobber:?}"
+    );
+}